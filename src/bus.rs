@@ -0,0 +1,20 @@
+use crate::memory::MemoryError;
+
+// Everything the CPU reads and writes goes through a bus, which decodes the 16-bit address to
+// RAM, ROM or device registers. `Memory` is the plain 64K of RAM used by default.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+
+    fn write(&mut self, address: u16, value: u8);
+
+    // Opcode fetches, which the 6502 signals on its SYNC pin, so a bus can tell code from data
+    fn fetch(&mut self, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    // Failed accesses are reported here instead of stopping the access, the CPU checks it after
+    // every instruction
+    fn take_error(&mut self) -> Option<MemoryError> {
+        None
+    }
+}
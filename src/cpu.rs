@@ -1,10 +1,170 @@
-use crate::instructions::{AddressingMode, Instruction};
-use crate::memory::Memory;
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::bus::Bus;
+use crate::cycle::CycleState;
+use crate::instructions::{AddressingMode, Instruction, Operation};
+use crate::memory::{Memory, MemoryError};
+use crate::port::IoPort;
 use crate::registers::Registers;
+use crate::variant::{Mos6502, Variant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Running,
+    // Executed a JAM instruction, only a reset gets the CPU going again
+    Jammed,
+    // Executed WAI, any interrupt gets the CPU going again
+    Waiting,
+    // Executed STP, only a reset gets the CPU going again
+    Stopped,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuError {
+    // The variant has no instruction for the opcode at `pc`
+    IllegalOpcode { pc: u16, opcode: u8 },
+    // The bus failed an access of the instruction at `pc`
+    Memory { pc: u16, error: MemoryError },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::IllegalOpcode { pc, opcode } => {
+                write!(f, "illegal opcode {:#04X} at {:#06X}", opcode, pc)
+            }
+            CpuError::Memory { pc, error } => {
+                write!(f, "instruction at {:#06X} failed: {}", pc, error)
+            }
+        }
+    }
+}
+
+impl Error for CpuError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CpuError::Memory { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// What a call to `step` did. Interrupt sequences have no instruction, and neither has a step of
+// a CPU that is not running, which takes no cycles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StepResult {
+    pub instruction: Option<Instruction>,
+    pub address: u16, // of the opcode, or where the interrupt was taken
+    pub cycles: u8,
+    pub interrupt: Option<Interrupt>,
+}
+
+// Why `run` returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    IllegalOpcode { pc: u16, opcode: u8 },
+    MemoryError { pc: u16, error: MemoryError },
+    // Executed JAM, WAI or STP, see `State`
+    Jammed,
+    Waiting,
+    Stopped,
+    // About to execute the instruction at a breakpoint
+    Breakpoint(u16),
+    // Ran out of the cycles or instructions it was given
+    BudgetExhausted,
+    // Asked to through a `StopHandle`
+    HostRequest,
+    // The condition of `run_until` was met
+    Condition,
+}
+
+// Limit on how long a bounded run may take. Interrupt sequences count as instructions, and the
+// instruction crossing a cycle budget is finished.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Budget {
+    Cycles(u64),
+    Instructions(u64),
+    Unlimited,
+}
+
+// How far a run got and why it returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunResult {
+    pub reason: StopReason,
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+impl From<CpuError> for StopReason {
+    fn from(error: CpuError) -> Self {
+        match error {
+            CpuError::IllegalOpcode { pc, opcode } => StopReason::IllegalOpcode { pc, opcode },
+            CpuError::Memory { pc, error } => StopReason::MemoryError { pc, error },
+        }
+    }
+}
+
+// Lets the host or device callbacks ask `run` to return before its next instruction, e.g. from
+// the write handler of an exit register
+#[derive(Clone, Debug, Default)]
+pub struct StopHandle {
+    requested: Rc<Cell<bool>>,
+}
 
-pub struct CPU {
-    pub memory: Memory,
+impl StopHandle {
+    pub fn stop(&self) {
+        self.requested.set(true);
+    }
+
+    pub(crate) fn take(&self) -> bool {
+        self.requested.replace(false)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    Reset,
+    Nmi,
+    Irq,
+}
+
+impl Interrupt {
+    pub fn vector(self) -> u16 {
+        match self {
+            Self::Nmi => 0xFFFA,
+            Self::Reset => 0xFFFC,
+            Self::Irq => 0xFFFE,
+        }
+    }
+}
+
+pub struct CPU<B: Bus = Memory> {
+    variant: Box<dyn Variant>,
+    pub memory: B,
     pub registers: Registers,
+    pub cycles: u64,
+    pub state: State,
+    // Value ORed with the accumulator by ANE and LXA, differs per chip and even temperature
+    pub magic: u8,
+    pub(crate) cycle_state: CycleState,
+    pub(crate) irq: bool,
+    nmi: bool,
+    pub(crate) nmi_pending: bool,
+    reset_pending: bool,
+    so: bool,
+    so_pending: bool,
+    // CLI, SEI and PLP change the interrupt disable flag after polling, so the next poll still
+    // sees its old value
+    pub(crate) delayed_interrupt_flag: Option<bool>,
+    pub(crate) stolen_cycles: u32,
+    io_port: Option<IoPort>,
+    // Addresses `run` stops at before executing the instruction there
+    pub breakpoints: HashSet<u16>,
+    stop_handle: StopHandle,
 }
 
 impl Default for CPU {
@@ -13,307 +173,796 @@ impl Default for CPU {
     }
 }
 
-impl CPU {
-    pub fn new(memory: Memory) -> Self {
+impl<B: Bus> CPU<B> {
+    pub fn new(memory: B) -> Self {
+        CPU::with_variant(memory, Mos6502)
+    }
+
+    pub fn with_variant(memory: B, variant: impl Variant + 'static) -> Self {
         CPU {
+            io_port: variant.io_port().then(IoPort::new),
+            variant: Box::new(variant),
             memory,
             registers: Registers::default(),
+            cycles: 0,
+            state: State::Running,
+            magic: 0xEE,
+            cycle_state: CycleState::default(),
+            irq: false,
+            nmi: false,
+            nmi_pending: false,
+            reset_pending: false,
+            so: false,
+            so_pending: false,
+            delayed_interrupt_flag: None,
+            stolen_cycles: 0,
+            breakpoints: HashSet::new(),
+            stop_handle: StopHandle::default(),
         }
     }
 
-    pub fn step(&mut self) {
-        if let Some(instruction) = self.fetch() {
-            self.execute(instruction);
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop_handle.clone()
+    }
+
+    pub fn variant(&self) -> &dyn Variant {
+        self.variant.as_ref()
+    }
+
+    // The on-chip I/O port, only present on the 6510
+    pub fn io_port(&mut self) -> Option<&mut IoPort> {
+        self.io_port.as_mut()
+    }
+
+    // The address as it appears on the address lines
+    pub(crate) fn bus_address(&self, address: u16) -> u16 {
+        address & self.variant.address_mask()
+    }
+
+    // All accesses made by the CPU itself go through here, so the I/O port can shadow memory.
+    // Stack accesses skip these as they never leave page one, which no variant remaps.
+    pub(crate) fn read_byte(&mut self, address: u16) -> u8 {
+        let address = self.bus_address(address);
+        read_bus(&mut self.memory, &self.io_port, self.cycles, address)
+    }
+
+    pub(crate) fn fetch_byte(&mut self, address: u16) -> u8 {
+        let address = self.bus_address(address);
+        match self.io_port.as_ref() {
+            Some(port) if IoPort::is_port_address(address) => port.read(address, self.cycles),
+            _ => self.memory.fetch(address),
         }
     }
 
-    pub fn run(&mut self) {
-        while let Some(instruction) = self.fetch() {
-            self.execute(instruction);
+    pub(crate) fn write_byte(&mut self, address: u16, value: u8) {
+        let address = self.bus_address(address);
+        match self.io_port.as_mut() {
+            Some(port) if IoPort::is_port_address(address) => {
+                port.write(address, value, self.cycles)
+            }
+            _ => self.memory.write(address, value),
         }
     }
 
-    fn fetch(&mut self) -> Option<Instruction> {
-        Instruction::decode(self.memory.get_byte(self.registers.pc))
+    // The IRQ line is level-triggered, it is serviced between instructions for as long as it is
+    // asserted and the interrupt disable flag is clear
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq = asserted && self.variant.interrupt_inputs();
     }
 
-    fn execute(&mut self, instruction: Instruction) {
-        let index = instruction
-            .addressing_mode()
-            .get_index(&self.memory, &mut self.registers);
+    // The NMI line is edge-triggered, only asserting it latches an interrupt
+    pub fn set_nmi(&mut self, asserted: bool) {
+        let asserted = asserted && self.variant.interrupt_inputs();
+        if asserted && !self.nmi {
+            self.nmi_pending = true;
+        }
+        self.nmi = asserted;
+    }
 
-        match instruction {
-            Instruction::ADC(_) => {
-                Instruction::adc(
-                    &mut self.registers.accumulator,
-                    &mut self.registers.status,
-                    self.memory.get_byte(index),
-                );
+    // The SO line sets the overflow flag when it gets asserted (its falling edge), before the next
+    // cycle or instruction runs
+    pub fn set_so(&mut self, asserted: bool) {
+        if asserted && !self.so {
+            self.so_pending = true;
+        }
+        self.so = asserted;
+    }
+
+    pub(crate) fn set_overflow_input(&mut self) {
+        if self.so_pending {
+            self.so_pending = false;
+            self.registers.status.overflow = true;
+        }
+    }
+
+    // Halts the CPU for the given number of cycles, like DMA pulling RDY low does. The CPU only
+    // halts on a read cycle, so `tick` first finishes the writes in progress.
+    pub fn steal_cycles(&mut self, cycles: u32) {
+        self.stolen_cycles += cycles;
+    }
+
+    // Aborts the current instruction, the reset sequence is run by the next `step` or `tick`
+    pub fn reset(&mut self) {
+        self.reset_pending = true;
+        self.cycle_state.abort();
+    }
+
+    pub(crate) fn pending_interrupt(&self) -> Option<Interrupt> {
+        if self.reset_pending {
+            return Some(Interrupt::Reset);
+        }
+
+        let interrupt_flag = self
+            .delayed_interrupt_flag
+            .unwrap_or(self.registers.status.interrupt);
+        self.poll(self.irq, self.nmi_pending, interrupt_flag)
+    }
+
+    // Interrupt to service for the given line states, NMI takes priority over IRQ
+    pub(crate) fn poll(&self, irq: bool, nmi: bool, interrupt_flag: bool) -> Option<Interrupt> {
+        if matches!(self.state, State::Jammed | State::Stopped) {
+            None
+        } else if nmi {
+            Some(Interrupt::Nmi)
+        } else if irq && !interrupt_flag {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
+    }
+
+    // Called at the start of an interrupt sequence, or when an NMI hijacks one
+    pub(crate) fn acknowledge(&mut self, interrupt: Interrupt) {
+        self.delayed_interrupt_flag = None;
+        if self.state == State::Waiting {
+            self.state = State::Running;
+        }
+        match interrupt {
+            Interrupt::Reset => {
+                self.reset_pending = false;
+                self.state = State::Running;
+                if let Some(port) = self.io_port.as_mut() {
+                    port.reset(self.cycles);
+                }
             }
-            Instruction::AND(_) => {
-                Instruction::and(
-                    &mut self.registers.accumulator,
-                    &mut self.registers.status,
-                    self.memory.get_byte(index),
-                );
+            Interrupt::Nmi => self.nmi_pending = false,
+            Interrupt::Irq => {}
+        }
+    }
+
+    // Runs the seven cycle interrupt sequence, a reset only decrements the stack pointer as its
+    // pushes are turned into reads
+    fn interrupt(&mut self, interrupt: Interrupt) -> u8 {
+        self.acknowledge(interrupt);
+
+        if interrupt == Interrupt::Reset {
+            self.registers.sp = self.registers.sp.wrapping_sub(3);
+            let vector = interrupt.vector();
+            self.registers.pc =
+                u16::from_le_bytes([self.read_byte(vector), self.read_byte(vector + 1)]);
+        } else {
+            let status = self.registers.status.to_stack(false);
+            let vector = self.bus_address(interrupt.vector());
+            Instruction::interrupt(&mut self.registers, &mut self.memory, vector, status);
+        }
+        self.enter_handler();
+
+        self.cycles += 7;
+        7
+    }
+
+    // Runs the next instruction or interrupt sequence. A CPU that is not running, e.g. waiting
+    // after WAI, takes no cycles. An instruction started with `tick` is finished first.
+    //
+    // An opcode the variant can not decode is an error and leaves the PC on it. So is an access
+    // the bus reports through `Bus::take_error`, after the instruction has completed.
+    //
+    // Interrupt lines changed between steps are seen at the next instruction boundary, except
+    // after a taken branch within a page. NMI hijacking BRK happens within an instruction and
+    // needs `tick`.
+    //
+    // Stolen cycles are added to `cycles` before the instruction, but not to the returned count.
+    pub fn step(&mut self) -> Result<StepResult, CpuError> {
+        let pc = self.registers.pc;
+        let result = self.step_instruction()?;
+        match self.memory.take_error() {
+            Some(error) => Err(CpuError::Memory { pc, error }),
+            None => Ok(result),
+        }
+    }
+
+    fn step_instruction(&mut self) -> Result<StepResult, CpuError> {
+        self.cycles += std::mem::take(&mut self.stolen_cycles) as u64;
+        self.set_overflow_input();
+
+        if self.cycle_state.in_progress() {
+            let (instruction, interrupt, address) = self.cycle_state.progress();
+            let mut cycles = 0;
+            while self.cycle_state.in_progress() {
+                self.tick()?;
+                cycles += 1;
             }
-            Instruction::ASL(_) => {
-                if *instruction.addressing_mode() == AddressingMode::Accumulator {
-                    Instruction::asl(&mut self.registers.status, &mut self.registers.accumulator);
-                } else {
-                    Instruction::asl(&mut self.registers.status, self.memory.get_byte_mut(index));
-                }
+            return Ok(StepResult {
+                instruction,
+                address,
+                cycles,
+                interrupt,
+            });
+        }
+
+        let pc = self.registers.pc;
+        if let Some(interrupt) = self.next_interrupt() {
+            return Ok(StepResult {
+                instruction: None,
+                address: pc,
+                cycles: self.interrupt(interrupt),
+                interrupt: Some(interrupt),
+            });
+        }
+
+        // A masked IRQ ends WAI without being serviced
+        if self.state == State::Waiting && self.irq {
+            self.state = State::Running;
+        }
+        if self.state != State::Running {
+            return Ok(StepResult {
+                instruction: None,
+                address: pc,
+                cycles: 0,
+                interrupt: None,
+            });
+        }
+
+        let opcode = self.fetch_byte(pc);
+        match self.decode(opcode) {
+            Some(instruction) => Ok(StepResult {
+                instruction: Some(instruction),
+                address: pc,
+                cycles: self.execute(instruction),
+                interrupt: None,
+            }),
+            None => Err(CpuError::IllegalOpcode { pc, opcode }),
+        }
+    }
+
+    // Steps until the CPU stops running, hits a breakpoint, fails or is asked to stop. A
+    // breakpoint at the PC `run` starts from is stepped over, so it can resume from there.
+    pub fn run(&mut self) -> StopReason {
+        self.run_until(Budget::Unlimited, |_| false).reason
+    }
+
+    // Like `run`, but also returns after the budget is used up, e.g. to run a video frame. Cycles
+    // stolen with `steal_cycles` use up the budget as well.
+    pub fn run_for_cycles(&mut self, cycles: u64) -> RunResult {
+        self.run_until(Budget::Cycles(cycles), |_| false)
+    }
+
+    pub fn run_for_instructions(&mut self, instructions: u64) -> RunResult {
+        self.run_until(Budget::Instructions(instructions), |_| false)
+    }
+
+    pub fn run_until_pc(&mut self, pc: u16, budget: Budget) -> RunResult {
+        self.run_until(budget, |cpu| cpu.registers.pc == pc)
+    }
+
+    // Like `run`, but also returns once `condition` holds before an instruction, which includes
+    // before the first one. The condition can look at registers and memory, e.g. wait for a
+    // test to store its result.
+    pub fn run_until(
+        &mut self,
+        budget: Budget,
+        mut condition: impl FnMut(&Self) -> bool,
+    ) -> RunResult {
+        let mut result = RunResult {
+            reason: StopReason::BudgetExhausted,
+            instructions: 0,
+            cycles: 0,
+        };
+
+        loop {
+            if self.stop_handle.take() {
+                result.reason = StopReason::HostRequest;
+                return result;
             }
-            Instruction::BCC(_) => {
-                let condition = !self.registers.status.carry;
-                Instruction::branch(&mut self.registers.pc, condition, index);
+            if condition(self) {
+                result.reason = StopReason::Condition;
+                return result;
             }
-            Instruction::BCS(_) => {
-                let condition = self.registers.status.carry;
-                Instruction::branch(&mut self.registers.pc, condition, index);
+            if result.instructions > 0 && self.breakpoints.contains(&self.registers.pc) {
+                result.reason = StopReason::Breakpoint(self.registers.pc);
+                return result;
             }
-            Instruction::BEQ(_) => {
-                let condition = self.registers.status.zero;
-                Instruction::branch(&mut self.registers.pc, condition, index);
+            let exhausted = match budget {
+                Budget::Cycles(cycles) => result.cycles >= cycles,
+                Budget::Instructions(instructions) => result.instructions >= instructions,
+                Budget::Unlimited => false,
+            };
+            if exhausted {
+                return result;
             }
-            Instruction::BIT(_) => {
-                Instruction::bit(
-                    &mut self.registers.accumulator,
-                    &mut self.registers.status,
-                    self.memory.get_byte(index),
-                );
+
+            // Counted from `cycles` rather than the step, which leaves out the cycles stolen by DMA
+            let cycles = self.cycles;
+            let step = self.step();
+            result.cycles += self.cycles - cycles;
+            match step {
+                Ok(step) => {
+                    if step.instruction.is_some() || step.interrupt.is_some() {
+                        result.instructions += 1;
+                    }
+                }
+                Err(error) => {
+                    result.reason = error.into();
+                    return result;
+                }
+            }
+            if let Some(reason) = self.halt_reason() {
+                result.reason = reason;
+                return result;
             }
-            Instruction::BMI(_) => {
-                let condition = self.registers.status.negative;
-                Instruction::branch(&mut self.registers.pc, condition, index);
+        }
+    }
+
+    fn halt_reason(&self) -> Option<StopReason> {
+        match self.state {
+            State::Running => None,
+            State::Jammed => Some(StopReason::Jammed),
+            State::Waiting => Some(StopReason::Waiting),
+            State::Stopped => Some(StopReason::Stopped),
+        }
+    }
+
+    pub(crate) fn decode(&self, opcode: u8) -> Option<Instruction> {
+        self.variant.decode(opcode)
+    }
+
+    // CMOS variants take an extra cycle to get valid flags from decimal ADC and SBC
+    pub(crate) fn decimal_cycle(&self, instruction: &Instruction) -> bool {
+        self.variant.cmos()
+            && self.registers.status.decimal
+            && matches!(instruction, Instruction::ADC(..) | Instruction::SBC(..))
+    }
+
+    // Indexed shifts of CMOS variants only take their last cycle when indexing crosses a page
+    pub(crate) fn shift_fixes_page(&self, instruction: &Instruction) -> bool {
+        self.variant.cmos()
+            && *instruction.addressing_mode() == AddressingMode::AbsoluteX
+            && matches!(
+                instruction,
+                Instruction::ASL(..)
+                    | Instruction::LSR(..)
+                    | Instruction::ROL(..)
+                    | Instruction::ROR(..)
+            )
+    }
+
+    // CMOS variants also clear the decimal flag when entering an interrupt handler
+    pub(crate) fn enter_handler(&mut self) {
+        self.registers.status.interrupt = true;
+        if self.variant.cmos() {
+            self.registers.status.decimal = false;
+        }
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> u8 {
+        let interrupt_flag = self.registers.status.interrupt;
+        self.delayed_interrupt_flag = None;
+
+        let (memory, io_port, cycles) = (&mut self.memory, &self.io_port, self.cycles);
+        let mask = self.variant.address_mask();
+        let (index, page_crossed) = instruction.addressing_mode().get_index(
+            |address| read_bus(memory, io_port, cycles, address & mask),
+            &mut self.registers,
+        );
+
+        // Even instructions a variant declares without cycles fetch their opcode
+        let mut cycles = instruction.cycles().max(1);
+
+        match instruction.operation() {
+            Operation::Read => {
+                if page_crossed {
+                    cycles += 1;
+                }
+                if self.decimal_cycle(&instruction) {
+                    cycles += 1;
+                }
+                let value = self.read_byte(index);
+                self.read_operation(&instruction, value);
             }
-            Instruction::BNE(_) => {
-                let condition = !self.registers.status.zero;
-                Instruction::branch(&mut self.registers.pc, condition, index);
+            Operation::Write => {
+                let (index, value) = self.write_operation(&instruction, index);
+                self.write_byte(index, value);
             }
-            Instruction::BPL(_) => {
-                let condition = !self.registers.status.negative;
-                Instruction::branch(&mut self.registers.pc, condition, index);
+            Operation::ReadModifyWrite => {
+                if page_crossed && self.shift_fixes_page(&instruction) {
+                    cycles += 1;
+                }
+                if *instruction.addressing_mode() == AddressingMode::Accumulator {
+                    let value = self.registers.accumulator;
+                    self.registers.accumulator = self.modify_operation(&instruction, value);
+                } else {
+                    let value = self.read_byte(index);
+                    let result = self.modify_operation(&instruction, value);
+                    self.write_byte(index, result);
+                }
             }
-            Instruction::BRK(_) => {
-                Instruction::brk(&mut self.registers, &mut self.memory);
+            Operation::Implied => self.implied_operation(&instruction),
+            Operation::Control => cycles += self.control_operation(&instruction, index),
+        }
+
+        // A taken branch within a page only polls at its operand fetch, so lines changed during
+        // the next step are seen one instruction late
+        if instruction.is_branch() && cycles == instruction.cycles() + 1 {
+            let interrupt = self.pending_interrupt();
+            self.cycle_state.set_poll(interrupt);
+        }
+
+        if instruction.delays_interrupt_flag() {
+            self.delayed_interrupt_flag = Some(interrupt_flag);
+        }
+
+        self.cycles += cycles as u64;
+        cycles
+    }
+
+    pub(crate) fn read_operation(&mut self, instruction: &Instruction, value: u8) {
+        let registers = &mut self.registers;
+        match instruction {
+            Instruction::ADC(..) => {
+                self.variant
+                    .adc(&mut registers.accumulator, &mut registers.status, value);
             }
-            Instruction::BVC(_) => {
-                let condition = !self.registers.status.overflow;
-                Instruction::branch(&mut self.registers.pc, condition, index);
+            Instruction::AND(..) => {
+                Instruction::and(&mut registers.accumulator, &mut registers.status, value);
             }
-            Instruction::BVS(_) => {
-                let condition = self.registers.status.overflow;
-                Instruction::branch(&mut self.registers.pc, condition, index);
+            Instruction::BIT(AddressingMode::Immediate, _) => {
+                registers.status.zero = (registers.accumulator & value) == 0;
             }
-            Instruction::CLC(_) => {
-                self.registers.status.carry = false;
+            Instruction::BIT(..) => {
+                Instruction::bit(&mut registers.accumulator, &mut registers.status, value);
             }
-            Instruction::CLD(_) => {
-                self.registers.status.decimal = false;
+            Instruction::CMP(..) => {
+                Instruction::compare(&mut registers.status, registers.accumulator, value);
             }
-            Instruction::CLI(_) => {
-                self.registers.status.interrupt = false;
+            Instruction::CPX(..) => {
+                Instruction::compare(&mut registers.status, registers.x, value);
             }
-            Instruction::CLV(_) => {
-                self.registers.status.overflow = false;
+            Instruction::CPY(..) => {
+                Instruction::compare(&mut registers.status, registers.y, value);
             }
-            Instruction::CMP(_) => {
-                let value_lhs = self.registers.accumulator;
-                Instruction::compare(
-                    &mut self.registers.status,
-                    value_lhs,
-                    self.memory.get_byte(index),
-                );
+            Instruction::EOR(..) => {
+                Instruction::eor(&mut registers.accumulator, &mut registers.status, value);
             }
-            Instruction::CPX(_) => {
-                let value_lhs = self.registers.x;
-                Instruction::compare(
-                    &mut self.registers.status,
-                    value_lhs,
-                    self.memory.get_byte(index),
-                );
+            Instruction::LDA(..) => {
+                Instruction::load(&mut registers.status, &mut registers.accumulator, value);
             }
-            Instruction::CPY(_) => {
-                let value_lhs = self.registers.y;
-                Instruction::compare(
-                    &mut self.registers.status,
-                    value_lhs,
-                    self.memory.get_byte(index),
-                );
+            Instruction::LDX(..) => {
+                Instruction::load(&mut registers.status, &mut registers.x, value);
             }
-            Instruction::DEC(_) => {
-                Instruction::decrement(&mut self.registers.status, self.memory.get_byte_mut(index));
+            Instruction::LDY(..) => {
+                Instruction::load(&mut registers.status, &mut registers.y, value);
             }
-            Instruction::DEX(_) => {
-                Instruction::decrement(&mut self.registers.status, &mut self.registers.x);
+            Instruction::ORA(..) => {
+                Instruction::ora(&mut registers.accumulator, &mut registers.status, value);
             }
-            Instruction::DEY(_) => {
-                Instruction::decrement(&mut self.registers.status, &mut self.registers.y);
+            Instruction::SBC(..) | Instruction::USBC(..) => {
+                self.variant
+                    .sbc(&mut registers.accumulator, &mut registers.status, value);
             }
-            Instruction::EOR(_) => {
-                Instruction::eor(
-                    &mut self.registers.accumulator,
-                    &mut self.registers.status,
-                    self.memory.get_byte(index),
-                );
+            Instruction::NOP(..) => {}
+            Instruction::ALR(..) => {
+                Instruction::and(&mut registers.accumulator, &mut registers.status, value);
+                Instruction::lsr(&mut registers.status, &mut registers.accumulator);
             }
-            Instruction::INC(_) => {
-                Instruction::increment(&mut self.registers.status, self.memory.get_byte_mut(index));
+            Instruction::ANC(..) => {
+                Instruction::and(&mut registers.accumulator, &mut registers.status, value);
+                registers.status.carry = registers.status.negative;
             }
-            Instruction::INX(_) => {
-                Instruction::increment(&mut self.registers.status, &mut self.registers.x);
+            Instruction::ARR(..) => {
+                let decimal = registers.status.decimal && self.variant.decimal_mode();
+                Instruction::arr(
+                    &mut registers.accumulator,
+                    &mut registers.status,
+                    value,
+                    decimal,
+                );
             }
-            Instruction::INY(_) => {
-                Instruction::increment(&mut self.registers.status, &mut self.registers.y);
+            Instruction::LAX(..) => {
+                Instruction::load(&mut registers.status, &mut registers.accumulator, value);
+                registers.x = value;
             }
-            Instruction::JMP(_) => {
-                self.registers.pc = index;
+            Instruction::SBX(..) => {
+                Instruction::sbx(registers, value);
             }
-            Instruction::JSR(_) => {
-                Instruction::jsr(&mut self.registers, &mut self.memory, index);
+            Instruction::ANE(..) => {
+                let result = (registers.accumulator | self.magic) & registers.x & value;
+                Instruction::load(&mut registers.status, &mut registers.accumulator, result);
             }
-            Instruction::LDA(_) => {
+            Instruction::LAS(..) => {
+                registers.sp &= value;
+                registers.x = registers.sp;
                 Instruction::load(
-                    &mut self.registers.status,
-                    &mut self.registers.accumulator,
-                    self.memory.get_byte(index),
+                    &mut registers.status,
+                    &mut registers.accumulator,
+                    registers.sp,
                 );
             }
-            Instruction::LDX(_) => {
-                Instruction::load(
-                    &mut self.registers.status,
-                    &mut self.registers.x,
-                    self.memory.get_byte(index),
-                );
+            Instruction::LXA(..) => {
+                let result = (registers.accumulator | self.magic) & value;
+                Instruction::load(&mut registers.status, &mut registers.accumulator, result);
+                registers.x = result;
             }
-            Instruction::LDY(_) => {
-                Instruction::load(
-                    &mut self.registers.status,
-                    &mut self.registers.y,
-                    self.memory.get_byte(index),
-                );
+            _ => unreachable!("{:?} is not a read instruction", instruction),
+        }
+    }
+
+    // Returns the address and value to write, which only differs from `index` for the SH* stores
+    pub(crate) fn write_operation(&mut self, instruction: &Instruction, index: u16) -> (u16, u8) {
+        let registers = &mut self.registers;
+        match instruction {
+            Instruction::STA(..) => (index, registers.accumulator),
+            Instruction::STX(..) => (index, registers.x),
+            Instruction::STY(..) => (index, registers.y),
+            Instruction::SAX(..) => (index, registers.accumulator & registers.x),
+            Instruction::STZ(..) => (index, 0),
+            Instruction::SHA(..) => {
+                let value = registers.accumulator & registers.x;
+                Instruction::high_byte_store(value, index, registers.y)
             }
-            Instruction::LSR(_) => {
-                if *instruction.addressing_mode() == AddressingMode::Accumulator {
-                    Instruction::lsr(&mut self.registers.status, &mut self.registers.accumulator);
-                } else {
-                    Instruction::lsr(&mut self.registers.status, self.memory.get_byte_mut(index));
-                }
+            Instruction::SHX(..) => Instruction::high_byte_store(registers.x, index, registers.y),
+            Instruction::SHY(..) => Instruction::high_byte_store(registers.y, index, registers.x),
+            Instruction::TAS(..) => {
+                registers.sp = registers.accumulator & registers.x;
+                Instruction::high_byte_store(registers.sp, index, registers.y)
             }
-            Instruction::NOP(_) => {}
-            Instruction::ORA(_) => {
-                Instruction::ora(
-                    &mut self.registers.accumulator,
-                    &mut self.registers.status,
-                    self.memory.get_byte(index),
-                );
+            _ => unreachable!("{:?} is not a write instruction", instruction),
+        }
+    }
+
+    pub(crate) fn modify_operation(&mut self, instruction: &Instruction, value: u8) -> u8 {
+        let registers = &mut self.registers;
+        let status = &mut registers.status;
+        let mut value = value;
+        match instruction {
+            Instruction::ASL(..) => Instruction::asl(status, &mut value),
+            Instruction::DEC(..) => Instruction::decrement(status, &mut value),
+            Instruction::INC(..) => Instruction::increment(status, &mut value),
+            Instruction::LSR(..) => Instruction::lsr(status, &mut value),
+            Instruction::ROL(..) => Instruction::rol(status, &mut value),
+            Instruction::ROR(..) => self.variant.ror(status, &mut value),
+            Instruction::DCP(..) => {
+                Instruction::decrement(status, &mut value);
+                Instruction::compare(status, registers.accumulator, value);
             }
-            Instruction::PHA(_) => {
-                self.registers
-                    .push(self.registers.accumulator, &mut self.memory);
+            Instruction::ISC(..) => {
+                Instruction::increment(status, &mut value);
+                self.variant.sbc(&mut registers.accumulator, status, value);
             }
-            Instruction::PHP(_) => {
-                self.registers
-                    .push(self.registers.status.to_binary() | 0x30, &mut self.memory);
+            Instruction::RLA(..) => {
+                Instruction::rol(status, &mut value);
+                Instruction::and(&mut registers.accumulator, status, value);
             }
-            Instruction::PLA(_) => {
-                Instruction::pla(&mut self.registers, &self.memory);
+            Instruction::RRA(..) => {
+                self.variant.ror(status, &mut value);
+                self.variant.adc(&mut registers.accumulator, status, value);
             }
-            Instruction::PLP(_) => {
-                Instruction::plp(&mut self.registers, &self.memory);
+            Instruction::SLO(..) => {
+                Instruction::asl(status, &mut value);
+                Instruction::ora(&mut registers.accumulator, status, value);
             }
-            Instruction::ROL(_) => {
-                if *instruction.addressing_mode() == AddressingMode::Accumulator {
-                    Instruction::rol(&mut self.registers.status, &mut self.registers.accumulator);
-                } else {
-                    Instruction::rol(&mut self.registers.status, self.memory.get_byte_mut(index));
-                }
+            Instruction::SRE(..) => {
+                Instruction::lsr(status, &mut value);
+                Instruction::eor(&mut registers.accumulator, status, value);
             }
-            Instruction::ROR(_) => {
-                if *instruction.addressing_mode() == AddressingMode::Accumulator {
-                    Instruction::ror(&mut self.registers.status, &mut self.registers.accumulator);
-                } else {
-                    Instruction::ror(&mut self.registers.status, self.memory.get_byte_mut(index));
-                }
+            Instruction::TRB(..) => Instruction::trb(registers.accumulator, status, &mut value),
+            Instruction::TSB(..) => Instruction::tsb(registers.accumulator, status, &mut value),
+            Instruction::RMB0(..)
+            | Instruction::RMB1(..)
+            | Instruction::RMB2(..)
+            | Instruction::RMB3(..)
+            | Instruction::RMB4(..)
+            | Instruction::RMB5(..)
+            | Instruction::RMB6(..)
+            | Instruction::RMB7(..) => {
+                value &= !(1 << instruction.zero_page_bit().0);
             }
-            Instruction::RTI(_) => {
-                Instruction::rti(&mut self.registers, &self.memory);
+            Instruction::SMB0(..)
+            | Instruction::SMB1(..)
+            | Instruction::SMB2(..)
+            | Instruction::SMB3(..)
+            | Instruction::SMB4(..)
+            | Instruction::SMB5(..)
+            | Instruction::SMB6(..)
+            | Instruction::SMB7(..) => {
+                value |= 1 << instruction.zero_page_bit().0;
             }
-            Instruction::RTS(_) => {
-                Instruction::rts(&mut self.registers, &self.memory);
+            _ => unreachable!("{:?} is not a read-modify-write instruction", instruction),
+        }
+        value
+    }
+
+    pub(crate) fn implied_operation(&mut self, instruction: &Instruction) {
+        let registers = &mut self.registers;
+        match instruction {
+            Instruction::CLC(..) => {
+                registers.status.carry = false;
             }
-            Instruction::SBC(_) => {
-                Instruction::adc(
-                    &mut self.registers.accumulator,
-                    &mut self.registers.status,
-                    255 - self.memory.get_byte(index),
-                );
+            Instruction::CLD(..) => {
+                registers.status.decimal = false;
             }
-            Instruction::SEC(_) => {
-                self.registers.status.carry = true;
+            Instruction::CLI(..) => {
+                registers.status.interrupt = false;
             }
-            Instruction::SED(_) => {
-                self.registers.status.decimal = true;
+            Instruction::CLV(..) => {
+                registers.status.overflow = false;
             }
-            Instruction::SEI(_) => {
-                self.registers.status.interrupt = true;
+            Instruction::DEX(..) => {
+                Instruction::decrement(&mut registers.status, &mut registers.x);
             }
-            Instruction::STA(_) => {
-                *self.memory.get_byte_mut(index) = self.registers.accumulator;
+            Instruction::DEY(..) => {
+                Instruction::decrement(&mut registers.status, &mut registers.y);
             }
-            Instruction::STX(_) => {
-                *self.memory.get_byte_mut(index) = self.registers.x;
+            Instruction::INX(..) => {
+                Instruction::increment(&mut registers.status, &mut registers.x);
             }
-            Instruction::STY(_) => {
-                *self.memory.get_byte_mut(index) = self.registers.y;
+            Instruction::INY(..) => {
+                Instruction::increment(&mut registers.status, &mut registers.y);
             }
-            Instruction::TAX(_) => {
-                Instruction::transfer(
-                    &mut self.registers.status,
-                    self.registers.accumulator,
-                    &mut self.registers.x,
-                );
+            Instruction::NOP(..) => {}
+            Instruction::SEC(..) => {
+                registers.status.carry = true;
+            }
+            Instruction::SED(..) => {
+                registers.status.decimal = true;
             }
-            Instruction::TAY(_) => {
+            Instruction::SEI(..) => {
+                registers.status.interrupt = true;
+            }
+            Instruction::TAX(..) => {
                 Instruction::transfer(
-                    &mut self.registers.status,
-                    self.registers.accumulator,
-                    &mut self.registers.y,
+                    &mut registers.status,
+                    registers.accumulator,
+                    &mut registers.x,
                 );
             }
-            Instruction::TSX(_) => {
+            Instruction::TAY(..) => {
                 Instruction::transfer(
-                    &mut self.registers.status,
-                    self.registers.sp,
-                    &mut self.registers.x,
+                    &mut registers.status,
+                    registers.accumulator,
+                    &mut registers.y,
                 );
             }
-            Instruction::TXA(_) => {
+            Instruction::TSX(..) => {
+                Instruction::transfer(&mut registers.status, registers.sp, &mut registers.x);
+            }
+            Instruction::TXA(..) => {
                 Instruction::transfer(
-                    &mut self.registers.status,
-                    self.registers.x,
-                    &mut self.registers.accumulator,
+                    &mut registers.status,
+                    registers.x,
+                    &mut registers.accumulator,
                 );
             }
-            Instruction::TXS(_) => {
-                Instruction::txs(&mut self.registers);
+            Instruction::TXS(..) => {
+                Instruction::txs(registers);
             }
-            Instruction::TYA(_) => {
+            Instruction::TYA(..) => {
                 Instruction::transfer(
-                    &mut self.registers.status,
-                    self.registers.y,
-                    &mut self.registers.accumulator,
+                    &mut registers.status,
+                    registers.y,
+                    &mut registers.accumulator,
                 );
             }
+            _ => unreachable!("{:?} is not an implied instruction", instruction),
+        }
+    }
+
+    pub(crate) fn branch_condition(&self, instruction: &Instruction) -> bool {
+        let status = &self.registers.status;
+        match instruction {
+            Instruction::BCC(..) => !status.carry,
+            Instruction::BCS(..) => status.carry,
+            Instruction::BEQ(..) => status.zero,
+            Instruction::BMI(..) => status.negative,
+            Instruction::BNE(..) => !status.zero,
+            Instruction::BPL(..) => !status.negative,
+            Instruction::BVC(..) => !status.overflow,
+            Instruction::BVS(..) => status.overflow,
+            Instruction::BRA(..) => true,
+            _ => unreachable!("{:?} is not a branch instruction", instruction),
+        }
+    }
+
+    pub(crate) fn bit_branch_condition(&self, instruction: &Instruction, value: u8) -> bool {
+        let (bit, set) = instruction.zero_page_bit();
+        ((value >> bit) & 0x01 != 0) == set
+    }
+
+    // Returns the extra cycles taken by branches
+    fn control_operation(&mut self, instruction: &Instruction, index: u16) -> u8 {
+        let registers = &mut self.registers;
+        match instruction {
+            _ if instruction.is_branch() => {
+                let condition = self.branch_condition(instruction);
+                return Instruction::branch(&mut self.registers.pc, condition, index);
+            }
+            Instruction::BRK(..) => {
+                let vector = self.variant.address_mask() & 0xFFFE;
+                Instruction::brk(registers, &mut self.memory, vector);
+                self.enter_handler();
+            }
+            _ if instruction.is_bit_branch() => {
+                let value = self.read_byte(index);
+                let offset = self.read_byte(self.registers.pc.wrapping_sub(1));
+                let condition = self.bit_branch_condition(instruction, value);
+                return Instruction::branch(&mut self.registers.pc, condition, offset as i8 as u16);
+            }
+            Instruction::JAM(..) => {
+                self.state = State::Jammed;
+            }
+            Instruction::STP(..) => {
+                self.state = State::Stopped;
+            }
+            Instruction::WAI(..) => {
+                self.state = State::Waiting;
+            }
+            Instruction::JMP(..) => {
+                registers.pc = index;
+            }
+            Instruction::JSR(..) => {
+                Instruction::jsr(registers, &mut self.memory, index);
+            }
+            Instruction::PHA(..) => {
+                registers.push(registers.accumulator, &mut self.memory);
+            }
+            Instruction::PHP(..) => {
+                registers.push(registers.status.to_stack(true), &mut self.memory);
+            }
+            Instruction::PHX(..) => {
+                registers.push(registers.x, &mut self.memory);
+            }
+            Instruction::PHY(..) => {
+                registers.push(registers.y, &mut self.memory);
+            }
+            Instruction::PLA(..) => {
+                Instruction::pla(registers, &mut self.memory);
+            }
+            Instruction::PLX(..) => {
+                let value = registers.pop(&mut self.memory);
+                Instruction::load(&mut registers.status, &mut registers.x, value);
+            }
+            Instruction::PLY(..) => {
+                let value = registers.pop(&mut self.memory);
+                Instruction::load(&mut registers.status, &mut registers.y, value);
+            }
+            Instruction::PLP(..) => {
+                Instruction::plp(registers, &mut self.memory);
+            }
+            Instruction::RTI(..) => {
+                Instruction::rti(registers, &mut self.memory);
+            }
+            Instruction::RTS(..) => {
+                Instruction::rts(registers, &mut self.memory);
+            }
+            _ => unreachable!("{:?} is not a control instruction", instruction),
         }
+        0
+    }
+}
+
+fn read_bus(memory: &mut impl Bus, io_port: &Option<IoPort>, cycles: u64, address: u16) -> u8 {
+    match io_port {
+        Some(port) if IoPort::is_port_address(address) => port.read(address, cycles),
+        _ => memory.read(address),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::variant::{Mos6502RevA, Mos6507, Mos6510, Ricoh2A03, Rockwell65C02, Wdc65C02};
+    use std::cell::Cell;
     use std::fs::read;
+    use std::rc::Rc;
 
     fn load_bin(file_name: &str) -> Option<Vec<u8>> {
         // Load the binary file from disk
@@ -326,29 +975,1098 @@ mod tests {
         }
     }
 
+    // A CPU of the variant with the program at $200, where it starts
+    fn load(variant: impl Variant + 'static, program: &[u8]) -> CPU {
+        let mut cpu = CPU::with_variant(Memory::default(), variant);
+        cpu.memory.poke_bytes(0x200, program);
+        cpu.registers.pc = 0x200;
+        cpu
+    }
+
+    // Runs `test` on a CPU from `setup` once per core, with `step` running an instruction on the
+    // instruction-stepped core the first time and on the cycle-stepped core the second
+    fn both_cores<B: Bus>(setup: impl Fn() -> CPU<B>, test: impl Fn(&mut CPU<B>, fn(&mut CPU<B>))) {
+        for step in [step_instruction, tick_instruction] {
+            test(&mut setup(), step);
+        }
+    }
+
+    fn step_instruction<B: Bus>(cpu: &mut CPU<B>) {
+        cpu.step().unwrap();
+    }
+
+    fn tick_instruction<B: Bus>(cpu: &mut CPU<B>) {
+        cpu.tick().unwrap();
+        while cpu.cycle_state.in_progress() {
+            cpu.tick().unwrap();
+        }
+    }
+
     // Compiled without decimal
     #[test]
     pub fn functional_test() {
         let program = load_bin("6502_functional_test").unwrap();
         let mut cpu = CPU::default();
-        cpu.memory.write_bytes(0x0a, &program);
+        cpu.memory.poke_bytes(0x0a, &program);
         cpu.registers.pc = 0x400;
-        while cpu.registers.pc != 0x336D {
-            cpu.step();
-        }
+        let result = cpu.run_until_pc(0x336D, Budget::Cycles(100_000_000));
+        assert_eq!(result.reason, StopReason::Condition);
     }
 
     #[test]
     pub fn all_suite_a() {
         let program = load_bin("all_suite_a").unwrap();
         let mut cpu = CPU::default();
-        cpu.memory.write_bytes(0x4000, &program);
+        cpu.memory.poke_bytes(0x4000, &program);
         cpu.registers.pc = 0x400;
 
-        while cpu.registers.pc != 0x45C0 {
-            cpu.step();
+        let result = cpu.run_until_pc(0x45C0, Budget::Cycles(10_000));
+        assert_eq!(result.reason, StopReason::Condition);
+
+        assert_eq!(cpu.memory.peek(0x210), 0xFF);
+    }
+
+    // Assembled with all flag checks enabled
+    #[test]
+    pub fn decimal_test() {
+        let program = load_bin("6502_decimal_test").unwrap();
+        let mut cpu = CPU::default();
+        cpu.memory.poke_bytes(0x200, &program);
+        cpu.registers.pc = 0x200;
+
+        let result = cpu.run_until_pc(0x24B, Budget::Cycles(70_000_000));
+        assert_eq!(result.reason, StopReason::Condition);
+
+        assert_eq!(cpu.memory.peek(0x0B), 0x00);
+    }
+
+    // Runs the cycle-stepped core next to the instruction-stepped one until `end` is reached
+    fn run_ticked(
+        variant: impl Variant + Copy + 'static,
+        file_name: &str,
+        load_index: u16,
+        start: u16,
+        end: u16,
+    ) -> CPU {
+        let program = load_bin(file_name).unwrap();
+        let mut cpu = CPU::with_variant(Memory::default(), variant);
+        let mut ticked = CPU::with_variant(Memory::default(), variant);
+        cpu.memory.poke_bytes(load_index, &program);
+        ticked.memory.poke_bytes(load_index, &program);
+        cpu.registers.pc = start;
+        ticked.registers.pc = start;
+
+        while cpu.registers.pc != end {
+            cpu.step().unwrap();
+            loop {
+                let access = ticked.next_access();
+                assert_eq!(ticked.tick().unwrap().access, access);
+                if !ticked.cycle_state.in_progress() {
+                    break;
+                }
+            }
+
+            assert_eq!(cpu.registers.pc, ticked.registers.pc);
+            assert_eq!(cpu.registers.accumulator, ticked.registers.accumulator);
+            assert_eq!(cpu.registers.x, ticked.registers.x);
+            assert_eq!(cpu.registers.y, ticked.registers.y);
+            assert_eq!(cpu.registers.sp, ticked.registers.sp);
+            assert_eq!(
+                cpu.registers.status.to_binary(),
+                ticked.registers.status.to_binary()
+            );
+            assert_eq!(cpu.cycles, ticked.cycles);
+        }
+        ticked
+    }
+
+    #[test]
+    pub fn functional_test_ticked() {
+        run_ticked(Mos6502, "6502_functional_test", 0x0a, 0x400, 0x336D);
+    }
+
+    #[test]
+    pub fn all_suite_a_ticked() {
+        let cpu = run_ticked(Mos6502, "all_suite_a", 0x4000, 0x400, 0x45C0);
+        assert_eq!(cpu.memory.peek(0x210), 0xFF);
+    }
+
+    #[test]
+    pub fn cycles_page_crossing() {
+        let mut cpu = CPU::default();
+        // LDA $12F0,X ; LDA $12F0,X ; STA $1200,X
+        cpu.memory.poke_bytes(
+            0x200,
+            &[0xBD, 0xF0, 0x12, 0xBD, 0xF0, 0x12, 0x9D, 0x00, 0x12],
+        );
+        cpu.registers.pc = 0x200;
+
+        cpu.registers.x = 0x0F;
+        assert_eq!(cpu.step().unwrap().cycles, 4);
+        cpu.registers.x = 0x10;
+        assert_eq!(cpu.step().unwrap().cycles, 5);
+        assert_eq!(cpu.step().unwrap().cycles, 5);
+        assert_eq!(cpu.cycles, 14);
+    }
+
+    #[test]
+    pub fn cycles_branch() {
+        let mut cpu = CPU::default();
+        // BNE +2 ; BEQ +2 ; BEQ -9
+        cpu.memory.poke_bytes(0x200, &[0xD0, 0x02, 0xF0, 0x02]);
+        cpu.memory.poke_bytes(0x206, &[0xF0, 0xF7]);
+        cpu.registers.pc = 0x200;
+        cpu.registers.status.zero = true;
+
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.step().unwrap().cycles, 3);
+        assert_eq!(cpu.registers.pc, 0x206);
+        assert_eq!(cpu.step().unwrap().cycles, 4);
+        assert_eq!(cpu.registers.pc, 0x1FF);
+    }
+
+    #[test]
+    pub fn undocumented_instructions() {
+        let program = [
+            0xA9, 0xF0, // LDA #$F0
+            0xA2, 0x3C, // LDX #$3C
+            0x87, 0x10, // SAX $10
+            0xA7, 0x10, // LAX $10
+            0xC7, 0x11, // DCP $11
+            0xE7, 0x12, // ISC $12
+            0x07, 0x13, // SLO $13
+            0x1C, 0x34, 0x12, // NOP $1234,X
+            0x80, 0x00, // NOP #$00
+            0x1A, // NOP
+            0x0B, 0x80, // ANC #$80
+            0xCB, 0x01, // SBX #$01
+        ];
+        let setup = || {
+            let mut cpu = load(Mos6502, &program);
+            cpu.memory.poke_bytes(0x11, &[0x31, 0x0F, 0x81]);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            while cpu.registers.pc != 0x218 {
+                step(cpu);
+            }
+            assert_eq!(cpu.memory.peek(0x10), 0x30);
+            assert_eq!(cpu.memory.peek(0x11), 0x30);
+            assert_eq!(cpu.memory.peek(0x12), 0x10);
+            assert_eq!(cpu.memory.peek(0x13), 0x02);
+            assert_eq!(cpu.registers.accumulator, 0x00);
+            assert_eq!(cpu.registers.x, 0xFF);
+            assert!(!cpu.registers.status.carry);
+            assert!(cpu.registers.status.negative);
+            assert_eq!(cpu.cycles, 37);
+        });
+    }
+
+    #[test]
+    pub fn unstable_instructions() {
+        let mut cpu = CPU::default();
+        cpu.memory.poke_bytes(
+            0x200,
+            &[
+                0xAB, 0x3C, // LXA #$3C
+                0x8B, 0xFF, // ANE #$FF
+                0x9E, 0xF0, 0x12, // SHX $12F0,Y
+                0x9E, 0x00, 0x12, // SHX $1200,Y
+            ],
+        );
+        cpu.registers.pc = 0x200;
+        cpu.registers.accumulator = 0x01;
+        cpu.magic = 0xFF;
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.accumulator, 0x3C);
+        assert_eq!(cpu.registers.x, 0x3C);
+
+        cpu.magic = 0x00;
+        cpu.registers.x = 0x0F;
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.accumulator, 0x0C);
+
+        // Crossing into page $13 stores X & $13 to page X & $13
+        cpu.registers.x = 0x07;
+        cpu.registers.y = 0x20;
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory.peek(0x0310), 0x03);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.memory.peek(0x1220), 0x07 & 0x13);
+    }
+
+    #[test]
+    pub fn jam() {
+        let mut cpu = CPU::default();
+        // NOP ; JAM ; NOP
+        cpu.memory.poke_bytes(0x200, &[0xEA, 0x02, 0xEA]);
+        cpu.registers.pc = 0x200;
+
+        assert_eq!(cpu.run(), StopReason::Jammed);
+        assert_eq!(cpu.state, State::Jammed);
+        assert_eq!(cpu.registers.pc, 0x202);
+        assert_eq!(cpu.step().unwrap().cycles, 0);
+        assert_eq!(cpu.registers.pc, 0x202);
+    }
+
+    #[test]
+    pub fn decimal_test_65c02() {
+        let cpu = run_ticked(Wdc65C02, "65c02_decimal_test", 0x200, 0x200, 0x24B);
+        assert_eq!(cpu.memory.peek(0x0B), 0x00);
+    }
+
+    // Counts the reads of a device register at $D000
+    struct CounterBus {
+        memory: Memory,
+        counter: u8,
+    }
+
+    impl Bus for CounterBus {
+        fn read(&mut self, address: u16) -> u8 {
+            if address == 0xD000 {
+                self.counter += 1;
+                return self.counter;
+            }
+            self.memory.peek(address)
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.memory.poke(address, value);
+        }
+    }
+
+    #[test]
+    pub fn custom_bus() {
+        let program = [
+            0xAD, 0x00, 0xD0, // LDA $D000
+            0xAD, 0x00, 0xD0, // LDA $D000
+            0x85, 0x10, // STA $10
+        ];
+        let setup = || {
+            let mut memory = Memory::default();
+            memory.poke_bytes(0x200, &program);
+            let mut cpu = CPU::new(CounterBus { memory, counter: 0 });
+            cpu.registers.pc = 0x200;
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            for _ in 0..3 {
+                step(cpu);
+            }
+            assert_eq!(cpu.memory.counter, 2);
+            assert_eq!(cpu.memory.memory.peek(0x10), 2);
+        });
+    }
+
+    #[test]
+    pub fn memory_errors() {
+        let mut cpu = CPU::new(Memory::new(0x1000));
+        // LDA $1234
+        cpu.memory.poke_bytes(0x200, &[0xAD, 0x34, 0x12]);
+        cpu.registers.pc = 0x200;
+
+        let error = MemoryError::OutOfBounds {
+            index: 0x1234,
+            size: 0x1000,
+        };
+        assert_eq!(cpu.step(), Err(CpuError::Memory { pc: 0x200, error }));
+        assert_eq!(cpu.registers.pc, 0x203);
+
+        // The cycle-stepped core reports it in the cycle of the access
+        cpu.registers.pc = 0x200;
+        for _ in 0..3 {
+            cpu.tick().unwrap();
+        }
+        let error = Err(CpuError::Memory { pc: 0x200, error });
+        assert_eq!(cpu.tick(), error);
+        assert_eq!(cpu.tick().unwrap().address, 0x203);
+    }
+
+    // Only knows NOP
+    struct NopOnly;
+
+    impl Variant for NopOnly {
+        fn decode(&self, opcode: u8) -> Option<Instruction> {
+            (opcode == 0xEA).then_some(Instruction::NOP(AddressingMode::Implied, 2))
+        }
+    }
+
+    #[test]
+    pub fn illegal_opcode() {
+        let mut cpu = CPU::with_variant(Memory::default(), NopOnly);
+        cpu.memory.poke_bytes(0x200, &[0xEA, 0xA9]);
+        cpu.registers.pc = 0x200;
+
+        let error = CpuError::IllegalOpcode {
+            pc: 0x201,
+            opcode: 0xA9,
+        };
+        assert_eq!(cpu.run(), error.into());
+        assert_eq!(cpu.step(), Err(error));
+        assert_eq!(cpu.registers.pc, 0x201);
+        assert_eq!(cpu.tick(), Err(error));
+        assert_eq!(cpu.registers.pc, 0x201);
+    }
+
+    // A 65C02 whose NOP is declared without cycles
+    struct FreeNop;
+
+    impl Variant for FreeNop {
+        fn decode(&self, opcode: u8) -> Option<Instruction> {
+            match opcode {
+                0xEA => Some(Instruction::NOP(AddressingMode::Implied, 0)),
+                _ => Instruction::decode_65c02(opcode),
+            }
+        }
+    }
+
+    #[test]
+    pub fn zero_cycle_instructions() {
+        let mut cpu = CPU::with_variant(Memory::default(), FreeNop);
+        cpu.memory.poke_bytes(0x200, &[0xEA, 0xEA, 0xDB]); // NOP, NOP, STP
+        cpu.registers.pc = 0x200;
+
+        let result = cpu.run_for_cycles(100);
+        assert_eq!(result.reason, StopReason::Stopped);
+        assert_eq!((result.instructions, result.cycles), (3, 5));
+
+        cpu.reset();
+        cpu.step().unwrap();
+        cpu.registers.pc = 0x200;
+        cpu.tick().unwrap();
+        assert!(!cpu.cycle_state.in_progress());
+        assert_eq!(cpu.registers.pc, 0x201);
+    }
+
+    #[test]
+    pub fn mos_6502_rev_a_ror() {
+        let program = [
+            0x38, // SEC
+            0xA9, 0x81, // LDA #$81
+            0x6A, // ROR A
+            0x66, 0x10, // ROR $10
+        ];
+        let setup = || {
+            let mut cpu = load(Mos6502RevA, &program);
+            cpu.memory.poke(0x10, 0x40);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            for _ in 0..4 {
+                step(cpu);
+            }
+            assert_eq!(cpu.registers.accumulator, 0x02);
+            assert_eq!(cpu.memory.peek(0x10), 0x80);
+            assert!(cpu.registers.status.carry);
+            assert!(cpu.registers.status.negative);
+            assert_eq!(cpu.cycles, 11);
+        });
+    }
+
+    #[test]
+    pub fn rockwell_65c02_without_wai_and_stp() {
+        let setup = || {
+            // WAI, STP, RMB0 $10
+            let mut cpu = load(Rockwell65C02, &[0xCB, 0xDB, 0x07, 0x10]);
+            cpu.memory.poke(0x10, 0xFF);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            for _ in 0..3 {
+                step(cpu);
+            }
+            assert_eq!(cpu.state, State::Running);
+            assert_eq!(cpu.memory.peek(0x10), 0xFE);
+            assert_eq!(cpu.cycles, 7);
+        });
+    }
+
+    #[test]
+    pub fn ricoh_2a03_binary_arithmetic() {
+        let program = [
+            0xF8, // SED
+            0xA9, 0x09, // LDA #$09
+            0x69, 0x01, // ADC #$01
+            0x38, // SEC
+            0xE9, 0x01, // SBC #$01
+            0x6B, 0xFF, // ARR #$FF
+        ];
+
+        both_cores(
+            || load(Ricoh2A03, &program),
+            |cpu, step| {
+                step(cpu);
+                step(cpu);
+                step(cpu);
+                assert_eq!(cpu.registers.accumulator, 0x0A);
+                step(cpu);
+                step(cpu);
+                assert_eq!(cpu.registers.accumulator, 0x09);
+                step(cpu);
+                assert_eq!(cpu.registers.accumulator, 0x84);
+                assert!(cpu.registers.status.decimal);
+                assert_eq!(cpu.cycles, 12);
+            },
+        );
+    }
+
+    #[test]
+    pub fn mos_6510_io_port() {
+        let program = [
+            0xA9, 0x07, // LDA #$07
+            0x85, 0x00, // STA $00
+            0xA9, 0x05, // LDA #$05
+            0x85, 0x01, // STA $01
+            0xA5, 0x01, // LDA $01
+            0xE6, 0x01, // INC $01
+        ];
+        let lines = Rc::new(Cell::new(0));
+        let setup = || {
+            let mut cpu = load(Mos6510, &program);
+            let seen = lines.clone();
+            cpu.io_port()
+                .unwrap()
+                .on_write(move |value| seen.set(value));
+            cpu.memory.poke(0x01, 0xAA);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            for _ in 0..4 {
+                step(cpu);
+            }
+            assert_eq!(lines.get(), 0x15);
+            step(cpu);
+            assert_eq!(cpu.registers.accumulator, 0x15);
+            step(cpu);
+            assert_eq!(lines.get(), 0x16);
+            assert_eq!(cpu.memory.peek(0x01), 0xAA);
+
+            // A reset turns the port back into inputs
+            cpu.reset();
+            step(cpu);
+            assert_eq!(cpu.io_port().unwrap().direction(), 0x00);
+            assert_eq!(lines.get(), 0x17);
+        });
+    }
+
+    #[test]
+    pub fn mos_6507_address_mask() {
+        let program = [
+            0x58, // CLI
+            0xAD, 0x80, 0xF0, // LDA $F080
+            0x8D, 0x81, 0x20, // STA $2081
+            0x00, // BRK
+        ];
+        let setup = || {
+            let mut cpu = CPU::with_variant(Memory::default(), Mos6507);
+            cpu.memory.poke_bytes(0x1000, &program);
+            cpu.memory.poke(0x1080, 0x42);
+            cpu.memory.poke_bytes(0x1FFC, &[0x00, 0xF0, 0x00, 0xF1]);
+            cpu.set_irq(true);
+            cpu.set_nmi(true);
+            cpu.reset();
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0xF000);
+            for _ in 0..3 {
+                step(cpu);
+            }
+            assert_eq!(cpu.registers.accumulator, 0x42);
+            assert_eq!(cpu.memory.peek(0x81), 0x42);
+            assert_eq!(cpu.registers.pc, 0xF007);
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0xF100);
+        });
+    }
+
+    #[test]
+    pub fn wdc_65c02_instructions() {
+        let program = [
+            0xA9, 0x0F, // LDA #$0F
+            0x85, 0x10, // STA $10
+            0xA9, 0x03, // LDA #$03
+            0x04, 0x10, // TSB $10
+            0x14, 0x10, // TRB $10
+            0x64, 0x11, // STZ $11
+            0x1A, // INC A
+            0x3A, // DEC A
+            0xA2, 0x80, // LDX #$80
+            0xDA, // PHX
+            0x7A, // PLY
+            0x5A, // PHY
+            0xFA, // PLX
+            0x89, 0x00, // BIT #$00
+            0xB2, 0x20, // LDA ($20)
+            0x9E, 0x00, 0x12, // STZ $1200,X
+            0x80, 0x01, // BRA +1
+            0x00, // BRK, skipped
+        ];
+        let setup = || {
+            let mut cpu = load(Wdc65C02, &program);
+            cpu.memory.poke_bytes(0x10, &[0x00, 0xFF]);
+            cpu.memory.poke_bytes(0x20, &[0x34, 0x12]);
+            cpu.memory.poke(0x1234, 0x5A);
+            cpu.memory.poke(0x1280, 0xFF);
+            cpu.registers.sp = 0xFF;
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            while cpu.registers.pc != 0x21E {
+                step(cpu);
+            }
+
+            assert_eq!(cpu.cycles, 55);
+            assert_eq!(cpu.memory.peek(0x10), 0x0C);
+            assert_eq!(cpu.memory.peek(0x11), 0x00);
+            assert_eq!(cpu.memory.peek(0x1280), 0x00);
+            assert_eq!(cpu.registers.accumulator, 0x5A);
+            assert_eq!(cpu.registers.x, 0x80);
+            assert_eq!(cpu.registers.y, 0x80);
+            assert_eq!(cpu.registers.sp, 0xFF);
+        });
+    }
+
+    #[test]
+    pub fn wdc_65c02_bit_immediate() {
+        let mut cpu = CPU::with_variant(Memory::default(), Wdc65C02);
+        // BIT #$C0
+        cpu.memory.poke_bytes(0x200, &[0x89, 0xC0]);
+        cpu.registers.pc = 0x200;
+        cpu.registers.accumulator = 0x01;
+
+        cpu.step().unwrap();
+        assert!(cpu.registers.status.zero);
+        assert!(!cpu.registers.status.negative);
+        assert!(!cpu.registers.status.overflow);
+    }
+
+    #[test]
+    pub fn wdc_65c02_undefined_opcodes() {
+        for code in 0..=0xFF {
+            let instruction = Instruction::decode_65c02(code).unwrap();
+            assert!(
+                !matches!(
+                    instruction,
+                    Instruction::ALR(..)
+                        | Instruction::ANC(..)
+                        | Instruction::ANE(..)
+                        | Instruction::ARR(..)
+                        | Instruction::DCP(..)
+                        | Instruction::ISC(..)
+                        | Instruction::JAM(..)
+                        | Instruction::LAS(..)
+                        | Instruction::LAX(..)
+                        | Instruction::LXA(..)
+                        | Instruction::RLA(..)
+                        | Instruction::RRA(..)
+                        | Instruction::SAX(..)
+                        | Instruction::SBX(..)
+                        | Instruction::SHA(..)
+                        | Instruction::SHX(..)
+                        | Instruction::SHY(..)
+                        | Instruction::SLO(..)
+                        | Instruction::SRE(..)
+                        | Instruction::TAS(..)
+                        | Instruction::USBC(..)
+                ),
+                "{:02X} decodes to {:?}",
+                code,
+                instruction
+            );
+        }
+
+        // NOP #$00 ; NOP $1234 (8 cycles) ; NOP (1 cycle) ; NOP $1234 (4 cycles)
+        let program = [0x02, 0x00, 0x5C, 0x34, 0x12, 0x03, 0xDC, 0x34, 0x12];
+        both_cores(
+            || load(Wdc65C02, &program),
+            |cpu, step| {
+                for (pc, cycles) in [(0x202, 2), (0x205, 10), (0x206, 11), (0x209, 15)] {
+                    step(cpu);
+                    assert_eq!(cpu.registers.pc, pc);
+                    assert_eq!(cpu.cycles, cycles);
+                }
+            },
+        );
+    }
+
+    #[test]
+    pub fn wdc_65c02_timing() {
+        let program = [
+            0xF8, // SED
+            0x69, 0x01, // ADC #$01, 3 cycles
+            0x1E, 0x00, 0x12, // ASL $1200,X, 6 cycles
+            0x1E, 0xFF, 0x12, // ASL $12FF,X, 7 cycles
+            0xFE, 0x00, 0x12, // INC $1200,X, 7 cycles
+        ];
+        let setup = || {
+            let mut cpu = load(Wdc65C02, &program);
+            cpu.registers.x = 0x01;
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            for cycles in [2, 5, 11, 18, 25] {
+                step(cpu);
+                assert_eq!(cpu.cycles, cycles);
+            }
+        });
+    }
+
+    #[test]
+    pub fn wdc_65c02_interrupt_clears_decimal() {
+        let setup = || {
+            // SED ; BRK
+            let mut cpu = load(Wdc65C02, &[0xF8, 0x00]);
+            cpu.memory.poke_bytes(0xFFFE, &[0x00, 0x05]);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x500);
+            assert!(!cpu.registers.status.decimal);
+            assert_eq!(
+                cpu.memory.peek(0x100 + cpu.registers.sp as u16 + 1) & 0x08,
+                0x08
+            );
+        });
+    }
+
+    #[test]
+    pub fn wdc_65c02_bit_instructions() {
+        let program = [
+            0x07, 0x10, // RMB0 $10
+            0xF7, 0x10, // SMB7 $10
+            0xFF, 0x10, 0x02, // BBS7 $10,+2
+            0x00, 0x00, // BRK, skipped
+            0x0F, 0x10, 0x00, // BBR0 $10,+0
+            0x8F, 0x10, 0xF0, // BBS0 $10,-16, not taken
+        ];
+        let setup = || {
+            let mut cpu = load(Wdc65C02, &program);
+            cpu.memory.poke(0x10, 0x01);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            while cpu.registers.pc != 0x20F {
+                step(cpu);
+            }
+            assert_eq!(cpu.memory.peek(0x10), 0x80);
+            assert_eq!(cpu.cycles, 27);
+        });
+    }
+
+    #[test]
+    pub fn wait_for_interrupt() {
+        let mut cpu = interrupt_cpu();
+        cpu.variant = Box::new(Wdc65C02);
+        // WAI ; NOP ; WAI
+        cpu.memory.poke_bytes(0x200, &[0xCB, 0xEA, 0xCB]);
+        cpu.registers.status.interrupt = true;
+
+        assert_eq!(cpu.run(), StopReason::Waiting);
+        assert_eq!(cpu.state, State::Waiting);
+        assert_eq!(cpu.registers.pc, 0x201);
+        assert_eq!(cpu.cycles, 3);
+
+        // A masked IRQ continues after WAI
+        cpu.set_irq(true);
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.registers.pc, 0x202);
+
+        cpu.set_irq(false);
+        cpu.registers.status.interrupt = false;
+        assert_eq!(cpu.run(), StopReason::Waiting);
+        assert_eq!(cpu.state, State::Waiting);
+        cpu.set_irq(true);
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert_eq!(cpu.state, State::Running);
+        assert_eq!(cpu.registers.pc, 0x500);
+    }
+
+    #[test]
+    pub fn stop() {
+        let mut cpu = interrupt_cpu();
+        cpu.variant = Box::new(Wdc65C02);
+        // STP
+        cpu.memory.poke(0x200, 0xDB);
+
+        assert_eq!(cpu.run(), StopReason::Stopped);
+        assert_eq!(cpu.state, State::Stopped);
+        cpu.set_irq(true);
+        cpu.set_nmi(true);
+        assert_eq!(cpu.step().unwrap().cycles, 0);
+
+        cpu.reset();
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert_eq!(cpu.state, State::Running);
+        assert_eq!(cpu.registers.pc, 0x400);
+    }
+
+    #[test]
+    pub fn step_results() {
+        let mut cpu = interrupt_cpu();
+        let nop = Instruction::NOP(AddressingMode::Implied, 2);
+        let result = StepResult {
+            instruction: Some(nop),
+            address: 0x200,
+            cycles: 2,
+            interrupt: None,
+        };
+        assert_eq!(cpu.step(), Ok(result));
+
+        cpu.set_irq(true);
+        let result = StepResult {
+            instruction: None,
+            address: 0x201,
+            cycles: 7,
+            interrupt: Some(Interrupt::Irq),
+        };
+        assert_eq!(cpu.step(), Ok(result));
+
+        // Finishing an instruction started with `tick`
+        cpu.set_irq(false);
+        cpu.tick().unwrap();
+        let result = StepResult {
+            instruction: Some(nop),
+            address: 0x500,
+            cycles: 1,
+            interrupt: None,
+        };
+        assert_eq!(cpu.step(), Ok(result));
+    }
+
+    #[test]
+    pub fn breakpoints() {
+        let mut cpu = interrupt_cpu();
+        cpu.breakpoints.insert(0x204);
+
+        assert_eq!(cpu.run(), StopReason::Breakpoint(0x204));
+        assert_eq!(cpu.registers.pc, 0x204);
+        // Resuming steps over the breakpoint
+        cpu.memory.poke(0x206, 0xDB);
+        cpu.variant = Box::new(Wdc65C02);
+        assert_eq!(cpu.run(), StopReason::Stopped);
+        assert_eq!(cpu.registers.pc, 0x207);
+    }
+
+    #[test]
+    pub fn host_request() {
+        let mut cpu = interrupt_cpu();
+        let handle = cpu.stop_handle();
+        handle.stop();
+
+        assert_eq!(cpu.run(), StopReason::HostRequest);
+        assert_eq!(cpu.registers.pc, 0x200);
+        cpu.memory.poke(0x201, 0x02);
+        assert_eq!(cpu.run(), StopReason::Jammed);
+    }
+
+    #[test]
+    pub fn bounded_runs() {
+        let mut cpu = CPU::default();
+        // INC $10 ; JMP $0200
+        cpu.memory
+            .poke_bytes(0x200, &[0xE6, 0x10, 0x4C, 0x00, 0x02]);
+        cpu.registers.pc = 0x200;
+
+        let result = cpu.run_for_instructions(5);
+        assert_eq!(result.reason, StopReason::BudgetExhausted);
+        assert_eq!((result.instructions, result.cycles), (5, 21));
+        assert_eq!(cpu.registers.pc, 0x202);
+
+        // The instruction crossing the budget is finished
+        let result = cpu.run_for_cycles(20);
+        assert_eq!((result.instructions, result.cycles), (6, 24));
+        assert_eq!(cpu.memory.peek(0x10), 6);
+
+        let result = cpu.run_until(Budget::Unlimited, |cpu| cpu.memory.peek(0x10) == 10);
+        assert_eq!(result.reason, StopReason::Condition);
+        assert_eq!(result.instructions, 8);
+        assert_eq!(cpu.registers.pc, 0x202);
+
+        let result = cpu.run_until_pc(0x200, Budget::Instructions(0));
+        assert_eq!(result.reason, StopReason::BudgetExhausted);
+        let result = cpu.run_until_pc(0x200, Budget::Instructions(1));
+        assert_eq!(result.reason, StopReason::Condition);
+        assert_eq!(result.cycles, 3);
+    }
+
+    #[test]
+    pub fn indirect_jump_page_wrap() {
+        let setup = || {
+            // JMP ($10FF)
+            let mut cpu = load(Mos6502, &[0x6C, 0xFF, 0x10]);
+            cpu.memory.poke_bytes(0x10FF, &[0x34, 0x56]);
+            cpu.memory.poke(0x1000, 0x12);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x1234);
+        });
+    }
+
+    #[test]
+    pub fn wdc_65c02_indirect_jump() {
+        let setup = || {
+            // JMP ($10FF) ; JMP ($1000,X)
+            let mut cpu = load(Wdc65C02, &[0x6C, 0xFF, 0x10]);
+            cpu.memory.poke_bytes(0x10FF, &[0x34, 0x12]);
+            cpu.memory.poke_bytes(0x1234, &[0x7C, 0x00, 0x10]);
+            cpu.memory.poke_bytes(0x1002, &[0x00, 0x02]);
+            cpu.registers.x = 0x02;
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x1234);
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x200);
+            assert_eq!(cpu.cycles, 12);
+        });
+    }
+
+    #[test]
+    pub fn zero_page_pointer_wrap() {
+        let setup = || {
+            // LDA ($FF),Y ; LDA ($F0,X), both reading their pointer from $FF and $00
+            let mut cpu = load(Mos6502, &[0xB1, 0xFF, 0xA1, 0xF0]);
+            cpu.memory.poke(0xFF, 0x00);
+            cpu.memory.poke(0x00, 0x12);
+            cpu.memory.poke(0x100, 0x56);
+            cpu.memory.poke_bytes(0x1200, &[0xBB, 0xAA]);
+            cpu.registers.x = 0x0F;
+            cpu.registers.y = 0x01;
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            assert_eq!(cpu.registers.accumulator, 0xAA);
+
+            step(cpu);
+            assert_eq!(cpu.registers.accumulator, 0xBB);
+        });
+    }
+
+    #[test]
+    pub fn program_counter_wrap() {
+        let setup = || {
+            let mut cpu = CPU::default();
+            // LDA $1234 at $FFFE, wrapping to read its high byte from $0000
+            cpu.memory.poke_bytes(0xFFFE, &[0xAD, 0x34]);
+            cpu.memory.poke_bytes(0x00, &[0x12, 0xEA]);
+            cpu.memory.poke(0x1234, 0x42);
+            cpu.registers.pc = 0xFFFE;
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            assert_eq!(cpu.registers.accumulator, 0x42);
+            assert_eq!(cpu.registers.pc, 0x0001);
+        });
+    }
+
+    fn interrupt_cpu() -> CPU {
+        let mut cpu = CPU::default();
+        // NOP sleds for the program and the NMI, RESET and IRQ handlers
+        for index in [0x200, 0x300, 0x400, 0x500] {
+            cpu.memory.poke_bytes(index, &[0xEA; 0x10]);
         }
+        cpu.memory
+            .poke_bytes(0xFFFA, &[0x00, 0x03, 0x00, 0x04, 0x00, 0x05]);
+        cpu.registers.pc = 0x200;
+        cpu.registers.sp = 0xFF;
+        cpu
+    }
+
+    #[test]
+    pub fn irq() {
+        let mut cpu = interrupt_cpu();
+        cpu.registers.status.interrupt = true;
+        cpu.set_irq(true);
+
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.registers.pc, 0x201);
+
+        cpu.registers.status.interrupt = false;
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert_eq!(cpu.registers.pc, 0x500);
+        assert!(cpu.registers.status.interrupt);
+        assert_eq!(cpu.registers.sp, 0xFC);
+        assert_eq!(cpu.memory.peek(0x1FF), 0x02);
+        assert_eq!(cpu.memory.peek(0x1FE), 0x01);
+        assert_eq!(cpu.memory.peek(0x1FD) & 0x30, 0x20);
+    }
+
+    #[test]
+    pub fn brk() {
+        let setup = || {
+            let mut cpu = interrupt_cpu();
+            // BRK ; padding ; LDA #$FF ; PHA ; PLP with an RTI handler
+            cpu.memory
+                .poke_bytes(0x200, &[0x00, 0xEA, 0xA9, 0xFF, 0x48, 0x28]);
+            cpu.memory.poke(0x500, 0x40);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x500);
+            assert_eq!(cpu.memory.peek(0x1FD) & 0x30, 0x30);
+
+            for _ in 0..4 {
+                step(cpu);
+            }
+            assert_eq!(cpu.registers.pc, 0x206);
+            assert_eq!(cpu.registers.status.to_binary(), 0xEF);
+        });
+    }
+
+    #[test]
+    pub fn nmi() {
+        let mut cpu = interrupt_cpu();
+        cpu.registers.status.interrupt = true;
+        cpu.set_nmi(true);
+
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert_eq!(cpu.registers.pc, 0x300);
+
+        // Holding the line does not trigger again
+        cpu.set_nmi(true);
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        cpu.set_nmi(false);
+        cpu.set_nmi(true);
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert_eq!(cpu.registers.sp, 0xF9);
+    }
+
+    #[test]
+    pub fn set_overflow() {
+        let setup = || {
+            let mut cpu = interrupt_cpu();
+            // BVC * ; CLV
+            cpu.memory.poke_bytes(0x200, &[0x50, 0xFE, 0xB8]);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x200);
+            cpu.set_so(true);
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x202);
+
+            // Holding the line does not set the flag again
+            step(cpu);
+            cpu.set_so(true);
+            step(cpu);
+            assert!(!cpu.registers.status.overflow);
+        });
+    }
+
+    #[test]
+    pub fn steal_cycles() {
+        let mut cpu = interrupt_cpu();
+        cpu.steal_cycles(513);
+
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.cycles, 515);
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.cycles, 517);
+    }
+
+    #[test]
+    pub fn stolen_cycles_budget() {
+        let mut cpu = interrupt_cpu();
+        cpu.steal_cycles(100);
+
+        let result = cpu.run_for_cycles(50);
+        assert_eq!((result.instructions, result.cycles), (1, 102));
+        let result = cpu.run_for_cycles(4);
+        assert_eq!((result.instructions, result.cycles), (2, 4));
+        assert_eq!(cpu.cycles, 106);
+    }
+
+    #[test]
+    pub fn interrupt_flag_latency() {
+        let setup = || {
+            let mut cpu = interrupt_cpu();
+            // CLI
+            cpu.memory.poke(0x200, 0x58);
+            cpu.registers.status.interrupt = true;
+            cpu.set_irq(true);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x202);
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x500);
+        });
+
+        let mut cpu = interrupt_cpu();
+        // SEI
+        cpu.memory.poke(0x200, 0x78);
+        cpu.step().unwrap();
+        cpu.set_irq(true);
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert_eq!(cpu.registers.pc, 0x500);
+        assert_eq!(cpu.memory.peek(0x1FD) & 0x04, 0x04);
+    }
+
+    #[test]
+    pub fn branch_delay() {
+        let setup = || {
+            let mut cpu = interrupt_cpu();
+            // NOP ; BNE +0
+            cpu.memory.poke_bytes(0x200, &[0xEA, 0xD0, 0x00]);
+            cpu
+        };
+
+        both_cores(setup, |cpu, step| {
+            step(cpu);
+            step(cpu);
+            cpu.set_irq(true);
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x204);
+            step(cpu);
+            assert_eq!(cpu.registers.pc, 0x500);
+        });
+    }
+
+    #[test]
+    pub fn reset() {
+        let mut cpu = interrupt_cpu();
+        cpu.memory.poke(0x200, 0x02);
+        assert_eq!(cpu.run(), StopReason::Jammed);
+        assert_eq!(cpu.state, State::Jammed);
 
-        assert_eq!(cpu.memory.get_byte(0x210), 0xFF);
+        cpu.reset();
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert_eq!(cpu.state, State::Running);
+        assert_eq!(cpu.registers.pc, 0x400);
+        assert_eq!(cpu.registers.sp, 0xFC);
+        assert!(cpu.registers.status.interrupt);
+        assert_eq!(cpu.memory.peek(0x1FF), 0x00);
     }
 }
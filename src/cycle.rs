@@ -0,0 +1,1081 @@
+use crate::bus::Bus;
+use crate::cpu::{CpuError, Interrupt, State, CPU};
+use crate::instructions::{AddressingMode, Instruction, Operation};
+use crate::registers::Status;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BusAccess {
+    Read,
+    Write,
+    // Halted by a stolen cycle, WAI or STP, the bus is left to DMA
+    Stall,
+}
+
+// A single bus cycle, as seen by memory and peripherals
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BusCycle {
+    pub address: u16,
+    pub value: u8,
+    pub access: BusAccess,
+}
+
+impl Default for BusCycle {
+    fn default() -> Self {
+        BusCycle {
+            address: 0,
+            value: 0,
+            access: BusAccess::Read,
+        }
+    }
+}
+
+// Progress of the instruction executed by the cycle-stepped core
+#[derive(Default)]
+pub(crate) struct CycleState {
+    instruction: Option<Instruction>,
+    interrupt: Option<Interrupt>, // interrupt sequence run as a BRK
+    opcode_address: u16,          // PC at the opcode fetch
+    cycle: u8,                    // cycles done since the opcode fetch
+    operand_cycle: Option<u8>,    // cycle at which the effective address was known
+    address: u16,                 // effective address, or jump target
+    base: u16,                    // address before indexing
+    pointer: u8,                  // zero page pointer or branch offset
+    value: u8,                    // value being modified by read-modify-write instructions
+    last_cycle: u8,               // cycle ending a read that takes longer than its access
+    bus: BusCycle,                // last bus cycle
+    irq: bool,                    // IRQ line during the last cycle
+    nmi: bool,                    // NMI edge detected by the last cycle
+    polled: bool,                 // the current instruction polled the interrupt lines
+    poll: Option<Interrupt>,      // interrupt to service after the current instruction
+}
+
+impl CycleState {
+    pub(crate) fn in_progress(&self) -> bool {
+        self.instruction.is_some()
+    }
+
+    fn start(&mut self, instruction: Instruction, opcode_address: u16) {
+        *self = CycleState {
+            instruction: Some(instruction),
+            opcode_address,
+            bus: self.bus,
+            irq: self.irq,
+            nmi: self.nmi,
+            ..CycleState::default()
+        };
+    }
+
+    fn start_interrupt(&mut self, interrupt: Interrupt, pc: u16) {
+        self.start(Instruction::BRK(AddressingMode::Implied, 7), pc);
+        self.interrupt = Some(interrupt);
+    }
+
+    // Instruction being executed and where it started, for `step` finishing it
+    pub(crate) fn progress(&self) -> (Option<Instruction>, Option<Interrupt>, u16) {
+        match self.interrupt {
+            Some(_) => (None, self.interrupt, self.opcode_address),
+            None => (self.instruction, None, self.opcode_address),
+        }
+    }
+
+    fn finish(&mut self) {
+        self.instruction = None;
+    }
+
+    pub(crate) fn abort(&mut self) {
+        self.finish();
+    }
+
+    pub(crate) fn set_poll(&mut self, interrupt: Option<Interrupt>) {
+        self.polled = true;
+        self.poll = interrupt;
+    }
+
+    fn clear_poll(&mut self) {
+        self.polled = false;
+        self.poll = None;
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    // Runs a single clock cycle, doing exactly the bus access a real 6502 does in that cycle.
+    // This includes the dummy reads of indexed addressing and the double write of
+    // read-modify-write instructions.
+    //
+    // Interrupts are polled in the last cycle of an instruction, from the lines as they were
+    // during the cycle before. Branches poll in their operand fetch instead, and BRK and the
+    // interrupt sequences do not poll at all.
+    //
+    // Like `step`, an opcode the variant can not decode is an error that leaves the CPU fetching
+    // it, and so is an access the bus reports, blamed on the instruction the cycle belongs to.
+    pub fn tick(&mut self) -> Result<BusCycle, CpuError> {
+        let pc = match self.cycle_state.in_progress() {
+            true => self.cycle_state.opcode_address,
+            false => self.registers.pc,
+        };
+        let result = self.tick_cycle();
+        match self.memory.take_error() {
+            Some(error) => Err(CpuError::Memory { pc, error }),
+            None => result,
+        }
+    }
+
+    fn tick_cycle(&mut self) -> Result<BusCycle, CpuError> {
+        self.cycles += 1;
+        self.set_overflow_input();
+
+        if self.stolen_cycles > 0 && self.next_access() == BusAccess::Read {
+            self.stolen_cycles -= 1;
+            self.cycle_state.bus.access = BusAccess::Stall;
+            return Ok(self.cycle_state.bus);
+        }
+
+        // WAI ends on any interrupt, even a masked IRQ which is then not serviced
+        if self.state == State::Waiting && (self.irq || self.pending_interrupt().is_some()) {
+            self.state = State::Running;
+            self.cycle_state.clear_poll();
+        }
+
+        let mut result = Ok(());
+        let halted = self.pending_interrupt() != Some(Interrupt::Reset);
+        if self.state == State::Jammed && halted {
+            // The address bus is stuck on $FFFF until a reset
+            self.read_cycle(0xFFFF);
+        } else if matches!(self.state, State::Waiting | State::Stopped) && halted {
+            self.cycle_state.bus.access = BusAccess::Stall;
+        } else {
+            match self.cycle_state.instruction {
+                None => result = self.fetch_cycle(),
+                Some(instruction) => self.instruction_cycle(&instruction),
+            }
+        }
+
+        self.cycle_state.irq = self.irq;
+        self.cycle_state.nmi = self.nmi_pending;
+        result.map(|_| self.cycle_state.bus)
+    }
+
+    fn instruction_cycle(&mut self, instruction: &Instruction) {
+        let interrupt_flag = self.registers.status.interrupt;
+        self.cycle_state.cycle += 1;
+
+        match instruction.operation() {
+            Operation::Implied => self.implied_cycle(instruction),
+            Operation::Control => self.control_cycle(instruction),
+            _ => self.operand_cycle(instruction),
+        }
+
+        if self.cycle_state.in_progress() {
+            return;
+        }
+
+        if !instruction.is_branch() && !matches!(instruction, Instruction::BRK(..)) {
+            self.poll_cycle(interrupt_flag);
+        }
+        if instruction.delays_interrupt_flag() {
+            self.delayed_interrupt_flag = Some(interrupt_flag);
+        }
+    }
+
+    // Access done by the next cycle, without running it
+    pub(crate) fn next_access(&self) -> BusAccess {
+        let state = &self.cycle_state;
+        let instruction = match state.instruction {
+            Some(instruction) => instruction,
+            None => return BusAccess::Read,
+        };
+        let cycle = state.cycle + 1;
+        let operand_cycle = state
+            .operand_cycle
+            .map(|operand_cycle| cycle - operand_cycle);
+
+        let write = match instruction.operation() {
+            Operation::Write => operand_cycle == Some(1),
+            Operation::ReadModifyWrite if self.variant().cmos() => operand_cycle == Some(3),
+            Operation::ReadModifyWrite => matches!(operand_cycle, Some(2 | 3)),
+            Operation::Control => match instruction {
+                Instruction::PHA(..)
+                | Instruction::PHP(..)
+                | Instruction::PHX(..)
+                | Instruction::PHY(..) => cycle == 2,
+                Instruction::JSR(..) => cycle == 3 || cycle == 4,
+                Instruction::BRK(..) => {
+                    state.interrupt != Some(Interrupt::Reset) && (2..=4).contains(&cycle)
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+
+        if write {
+            BusAccess::Write
+        } else {
+            BusAccess::Read
+        }
+    }
+
+    fn poll_cycle(&mut self, interrupt_flag: bool) {
+        let state = &self.cycle_state;
+        let interrupt = self.poll(state.irq, state.nmi, interrupt_flag);
+
+        // A second poll of a branch can only upgrade an IRQ to an NMI
+        if self.cycle_state.poll != Some(Interrupt::Nmi) && interrupt.is_some() {
+            self.cycle_state.poll = interrupt;
+        }
+        self.cycle_state.polled = true;
+    }
+
+    fn read_cycle(&mut self, address: u16) -> u8 {
+        let address = self.bus_address(address);
+        let value = self.read_byte(address);
+        self.record_read(address, value)
+    }
+
+    fn opcode_cycle(&mut self, address: u16) -> u8 {
+        let address = self.bus_address(address);
+        let value = self.fetch_byte(address);
+        self.record_read(address, value)
+    }
+
+    fn record_read(&mut self, address: u16, value: u8) -> u8 {
+        self.cycle_state.bus = BusCycle {
+            address,
+            value,
+            access: BusAccess::Read,
+        };
+        value
+    }
+
+    fn write_cycle(&mut self, address: u16, value: u8) {
+        let address = self.bus_address(address);
+        self.write_byte(address, value);
+        self.cycle_state.bus = BusCycle {
+            address,
+            value,
+            access: BusAccess::Write,
+        };
+    }
+
+    fn push_cycle(&mut self, value: u8) {
+        self.write_cycle(0x100 + self.registers.sp as u16, value);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+    }
+
+    fn pull_cycle(&mut self) -> u8 {
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        self.read_cycle(0x100 + self.registers.sp as u16)
+    }
+
+    fn fetch_operand(&mut self) -> u8 {
+        let value = self.read_cycle(self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        value
+    }
+
+    // Interrupts replace the opcode fetch by a dummy read and then run the BRK sequence
+    fn interrupt_cycle(&mut self, interrupt: Interrupt) {
+        self.read_cycle(self.registers.pc);
+        self.acknowledge(interrupt);
+        self.cycle_state
+            .start_interrupt(interrupt, self.registers.pc);
+    }
+
+    // Interrupt to service before the next instruction, from the poll made by the last one.
+    // Without a poll the lines are checked as they are now.
+    pub(crate) fn next_interrupt(&mut self) -> Option<Interrupt> {
+        let pending = self.pending_interrupt();
+        let interrupt = match pending {
+            Some(Interrupt::Reset) => pending,
+            _ if self.cycle_state.polled => self.cycle_state.poll,
+            _ => pending,
+        };
+        self.cycle_state.clear_poll();
+        self.delayed_interrupt_flag = None;
+        interrupt
+    }
+
+    fn fetch_cycle(&mut self) -> Result<(), CpuError> {
+        if let Some(interrupt) = self.next_interrupt() {
+            self.interrupt_cycle(interrupt);
+            return Ok(());
+        }
+
+        let pc = self.registers.pc;
+        let opcode = self.opcode_cycle(pc);
+
+        // An unknown opcode leaves the CPU stuck fetching it, like `step` does
+        let instruction = self
+            .decode(opcode)
+            .ok_or(CpuError::IllegalOpcode { pc, opcode })?;
+        self.registers.pc = pc.wrapping_add(1);
+        self.cycle_state.start(instruction, pc);
+
+        // The single cycle NOPs of the 65C02 are done with the fetch, as are instructions a variant
+        // declares without cycles
+        if instruction.cycles() <= 1 {
+            self.cycle_state.finish();
+        }
+        Ok(())
+    }
+
+    fn implied_cycle(&mut self, instruction: &Instruction) {
+        self.read_cycle(self.registers.pc);
+        self.implied_operation(instruction);
+        self.cycle_state.finish();
+    }
+
+    fn operand_cycle(&mut self, instruction: &Instruction) {
+        let Some(operand_cycle) = self.cycle_state.operand_cycle else {
+            self.address_cycle(instruction);
+            return;
+        };
+
+        let address = self.cycle_state.address;
+        match (
+            instruction.operation(),
+            self.cycle_state.cycle - operand_cycle,
+        ) {
+            (Operation::Read, 1) => {
+                let value = self.read_cycle(address);
+                self.read_operation(instruction, value);
+                self.finish_read(instruction);
+            }
+            (Operation::Read, _) => {
+                self.read_cycle(self.registers.pc);
+                if self.cycle_state.cycle == self.cycle_state.last_cycle {
+                    self.cycle_state.finish();
+                }
+            }
+            (Operation::Write, 1) => {
+                let (address, value) = self.write_operation(instruction, address);
+                self.write_cycle(address, value);
+                self.cycle_state.finish();
+            }
+            (Operation::ReadModifyWrite, 1) => {
+                self.cycle_state.value = self.read_cycle(address);
+            }
+            (Operation::ReadModifyWrite, 2) => {
+                // The 65C02 reads again instead of writing the unmodified value
+                let value = self.cycle_state.value;
+                if self.variant().cmos() {
+                    self.read_cycle(address);
+                } else {
+                    self.write_cycle(address, value);
+                }
+                self.cycle_state.value = self.modify_operation(instruction, value);
+            }
+            (Operation::ReadModifyWrite, 3) => {
+                self.write_cycle(address, self.cycle_state.value);
+                self.cycle_state.finish();
+            }
+            _ => unreachable!("{:?} has no more cycles", instruction),
+        }
+    }
+
+    // Cycles computing the effective address, which go on the operand cycles once it is known
+    fn address_cycle(&mut self, instruction: &Instruction) {
+        let mode = *instruction.addressing_mode();
+        let pointer = self.cycle_state.pointer;
+
+        match (mode, self.cycle_state.cycle) {
+            (AddressingMode::Accumulator, 1) => {
+                self.read_cycle(self.registers.pc);
+                let value = self.registers.accumulator;
+                self.registers.accumulator = self.modify_operation(instruction, value);
+                self.cycle_state.finish();
+            }
+            (AddressingMode::Immediate, 1) => {
+                let value = self.fetch_operand();
+                self.read_operation(instruction, value);
+                self.finish_read(instruction);
+            }
+            (AddressingMode::ZeroPage, 1) => {
+                self.cycle_state.address = self.fetch_operand() as u16;
+                self.cycle_state.operand_cycle = Some(1);
+            }
+            (AddressingMode::ZeroPageX | AddressingMode::ZeroPageY, 1)
+            | (AddressingMode::IndirectX | AddressingMode::IndirectY, 1)
+            | (AddressingMode::ZeroPageIndirect, 1) => {
+                self.cycle_state.pointer = self.fetch_operand();
+            }
+            (AddressingMode::ZeroPageX | AddressingMode::ZeroPageY, 2) => {
+                self.read_cycle(pointer as u16);
+                let index = if mode == AddressingMode::ZeroPageX {
+                    self.registers.x
+                } else {
+                    self.registers.y
+                };
+                self.cycle_state.address = pointer.wrapping_add(index) as u16;
+                self.cycle_state.operand_cycle = Some(2);
+            }
+            (AddressingMode::Absolute, 1)
+            | (AddressingMode::AbsoluteX | AddressingMode::AbsoluteY, 1) => {
+                self.cycle_state.base = self.fetch_operand() as u16;
+            }
+            (AddressingMode::Absolute, 2) => {
+                let high = self.fetch_operand() as u16;
+                self.cycle_state.address = (high << 8) | self.cycle_state.base;
+                self.cycle_state.operand_cycle = Some(2);
+            }
+            (AddressingMode::AbsoluteX | AddressingMode::AbsoluteY, 2) => {
+                let high = self.fetch_operand() as u16;
+                let index = if mode == AddressingMode::AbsoluteX {
+                    self.registers.x
+                } else {
+                    self.registers.y
+                };
+                self.cycle_state.base |= high << 8;
+                self.cycle_state.address = self.cycle_state.base.wrapping_add(index as u16);
+            }
+            (AddressingMode::AbsoluteX | AddressingMode::AbsoluteY, 3)
+            | (AddressingMode::IndirectY, 4) => self.indexed_cycle(instruction),
+            (AddressingMode::IndirectX, 2) => {
+                self.read_cycle(pointer as u16);
+                self.cycle_state.pointer = pointer.wrapping_add(self.registers.x);
+            }
+            (AddressingMode::IndirectX, 3) => {
+                self.cycle_state.address = self.read_cycle(pointer as u16) as u16;
+            }
+            (AddressingMode::IndirectX, 4) => {
+                let high = self.read_cycle(pointer.wrapping_add(1) as u16) as u16;
+                self.cycle_state.address |= high << 8;
+                self.cycle_state.operand_cycle = Some(4);
+            }
+            (AddressingMode::ZeroPageIndirect, 2) => {
+                self.cycle_state.address = self.read_cycle(pointer as u16) as u16;
+            }
+            (AddressingMode::ZeroPageIndirect, 3) => {
+                let high = self.read_cycle(pointer.wrapping_add(1) as u16) as u16;
+                self.cycle_state.address |= high << 8;
+                self.cycle_state.operand_cycle = Some(3);
+            }
+            (AddressingMode::IndirectY, 2) => {
+                self.cycle_state.base = self.read_cycle(pointer as u16) as u16;
+            }
+            (AddressingMode::IndirectY, 3) => {
+                let high = self.read_cycle(pointer.wrapping_add(1) as u16) as u16;
+                self.cycle_state.base |= high << 8;
+                self.cycle_state.address =
+                    self.cycle_state.base.wrapping_add(self.registers.y as u16);
+            }
+            _ => unreachable!("{:?} has no more addressing cycles", instruction),
+        }
+    }
+
+    // Indexing first reads from the unfixed page. Reads use that value when no page was crossed,
+    // writes and read-modify-writes always treat it as a dummy read. The 65C02 reads the last
+    // operand byte instead of the unfixed address, and its indexed shifts also use the value.
+    fn indexed_cycle(&mut self, instruction: &Instruction) {
+        let (base, address) = (self.cycle_state.base, self.cycle_state.address);
+        let unfixed = (base & 0xFF00) | (address & 0x00FF);
+        let cycle = self.cycle_state.cycle;
+
+        if unfixed != address && self.variant().cmos() {
+            self.read_cycle(self.registers.pc.wrapping_sub(1));
+            self.cycle_state.operand_cycle = Some(cycle);
+            return;
+        }
+
+        let value = self.read_cycle(unfixed);
+        if unfixed != address {
+            self.cycle_state.operand_cycle = Some(cycle);
+        } else if instruction.operation() == Operation::Read {
+            self.read_operation(instruction, value);
+            self.finish_read(instruction);
+        } else if self.shift_fixes_page(instruction) {
+            self.cycle_state.value = value;
+            self.cycle_state.operand_cycle = Some(cycle - 1);
+        } else {
+            self.cycle_state.operand_cycle = Some(cycle);
+        }
+    }
+
+    // Reads end with the access, unless the instruction takes longer: decimal ADC and SBC of the
+    // 65C02 and some of its NOPs. Their extra cycles read the next opcode.
+    fn finish_read(&mut self, instruction: &Instruction) {
+        let cycle = self.cycle_state.cycle;
+        let last_cycle = (cycle + self.decimal_cycle(instruction) as u8)
+            .max(instruction.cycles().saturating_sub(1));
+
+        if cycle == last_cycle {
+            self.cycle_state.finish();
+        } else {
+            self.cycle_state.last_cycle = last_cycle;
+            self.cycle_state.operand_cycle = Some(cycle - 1);
+        }
+    }
+
+    fn control_cycle(&mut self, instruction: &Instruction) {
+        let cycle = self.cycle_state.cycle;
+        let pc = self.registers.pc;
+
+        match (instruction, cycle) {
+            _ if instruction.is_branch() => self.branch_cycle(instruction),
+            _ if instruction.is_bit_branch() => self.bit_branch_cycle(instruction),
+            (Instruction::STP(..) | Instruction::WAI(..), 1) => {
+                self.read_cycle(pc);
+            }
+            (Instruction::STP(..), 2) => {
+                self.read_cycle(pc);
+                self.state = State::Stopped;
+                self.cycle_state.finish();
+            }
+            (Instruction::WAI(..), 2) => {
+                self.read_cycle(pc);
+                self.state = State::Waiting;
+                self.cycle_state.finish();
+            }
+            (Instruction::JAM(..), 1) => {
+                self.read_cycle(pc);
+                self.state = State::Jammed;
+                self.cycle_state.finish();
+            }
+            (Instruction::BRK(..), _) => self.brk_cycle(),
+            (Instruction::JMP(..) | Instruction::JSR(..), 1) => {
+                self.cycle_state.address = self.fetch_operand() as u16;
+            }
+            (
+                Instruction::JMP(
+                    AddressingMode::AbsoluteIndirect | AddressingMode::AbsoluteIndirectX,
+                    _,
+                ),
+                _,
+            ) => self.indirect_jump_cycle(instruction),
+            (Instruction::JMP(AddressingMode::Absolute, _), 2)
+            | (Instruction::JMP(AddressingMode::Indirect, _), 4)
+            | (Instruction::JSR(..), 5) => {
+                // Indirect jumps fetch the high byte without carrying into the page
+                let address = self.cycle_state.address;
+                let high_address = match instruction {
+                    Instruction::JMP(AddressingMode::Indirect, _) => {
+                        (self.cycle_state.base & 0xFF00)
+                            | (self.cycle_state.base.wrapping_add(1) & 0x00FF)
+                    }
+                    _ => pc,
+                };
+                let high = self.read_cycle(high_address) as u16;
+                self.registers.pc = (high << 8) | (address & 0x00FF);
+                self.cycle_state.finish();
+            }
+            (Instruction::JSR(..), 3) => {
+                self.push_cycle((pc >> 8) as u8);
+            }
+            (Instruction::JSR(..), 4) => {
+                self.push_cycle(pc as u8);
+            }
+            (Instruction::JMP(..), 2) => {
+                let high = self.fetch_operand() as u16;
+                self.cycle_state.base = (high << 8) | self.cycle_state.address;
+            }
+            (Instruction::JMP(..), 3) => {
+                self.cycle_state.address = self.read_cycle(self.cycle_state.base) as u16;
+            }
+            (Instruction::JSR(..), 2)
+            | (Instruction::PLA(..) | Instruction::PLP(..), 2)
+            | (Instruction::PLX(..) | Instruction::PLY(..), 2)
+            | (Instruction::RTI(..) | Instruction::RTS(..), 2) => {
+                self.read_cycle(0x100 + self.registers.sp as u16);
+            }
+            (
+                Instruction::PHA(..)
+                | Instruction::PHP(..)
+                | Instruction::PHX(..)
+                | Instruction::PHY(..)
+                | Instruction::PLA(..)
+                | Instruction::PLP(..)
+                | Instruction::PLX(..)
+                | Instruction::PLY(..)
+                | Instruction::RTI(..)
+                | Instruction::RTS(..),
+                1,
+            ) => {
+                self.read_cycle(pc);
+            }
+            (Instruction::PHA(..), 2) => {
+                self.push_cycle(self.registers.accumulator);
+                self.cycle_state.finish();
+            }
+            (Instruction::PHX(..), 2) => {
+                self.push_cycle(self.registers.x);
+                self.cycle_state.finish();
+            }
+            (Instruction::PHY(..), 2) => {
+                self.push_cycle(self.registers.y);
+                self.cycle_state.finish();
+            }
+            (Instruction::PHP(..), 2) => {
+                self.push_cycle(self.registers.status.to_stack(true));
+                self.cycle_state.finish();
+            }
+            (Instruction::PLA(..), 3) => {
+                let value = self.pull_cycle();
+                Instruction::load(
+                    &mut self.registers.status,
+                    &mut self.registers.accumulator,
+                    value,
+                );
+                self.cycle_state.finish();
+            }
+            (Instruction::PLX(..), 3) => {
+                let value = self.pull_cycle();
+                Instruction::load(&mut self.registers.status, &mut self.registers.x, value);
+                self.cycle_state.finish();
+            }
+            (Instruction::PLY(..), 3) => {
+                let value = self.pull_cycle();
+                Instruction::load(&mut self.registers.status, &mut self.registers.y, value);
+                self.cycle_state.finish();
+            }
+            (Instruction::PLP(..), 3) => {
+                self.registers.status = Status::from_binary(self.pull_cycle());
+                self.cycle_state.finish();
+            }
+            (Instruction::RTI(..), 3) => {
+                self.registers.status = Status::from_binary(self.pull_cycle());
+            }
+            (Instruction::RTI(..), 4) | (Instruction::RTS(..), 3) => {
+                self.cycle_state.address = self.pull_cycle() as u16;
+            }
+            (Instruction::RTI(..), 5) => {
+                let high = self.pull_cycle() as u16;
+                self.registers.pc = (high << 8) | self.cycle_state.address;
+                self.cycle_state.finish();
+            }
+            (Instruction::RTS(..), 4) => {
+                let high = self.pull_cycle() as u16;
+                self.cycle_state.address |= high << 8;
+            }
+            (Instruction::RTS(..), 5) => {
+                let address = self.cycle_state.address;
+                self.read_cycle(address);
+                self.registers.pc = address.wrapping_add(1);
+                self.cycle_state.finish();
+            }
+            _ => unreachable!("{:?} has no more cycles", instruction),
+        }
+    }
+
+    // 65C02 indirect jumps take a cycle to add the index, or to fix the page bug, and read the
+    // pointer across pages
+    fn indirect_jump_cycle(&mut self, instruction: &Instruction) {
+        match self.cycle_state.cycle {
+            2 => {
+                let high = self.fetch_operand() as u16;
+                self.cycle_state.base = (high << 8) | self.cycle_state.address;
+                if *instruction.addressing_mode() == AddressingMode::AbsoluteIndirectX {
+                    self.cycle_state.base =
+                        self.cycle_state.base.wrapping_add(self.registers.x as u16);
+                }
+            }
+            3 => {
+                self.read_cycle(self.registers.pc.wrapping_sub(1));
+            }
+            4 => {
+                self.cycle_state.address = self.read_cycle(self.cycle_state.base) as u16;
+            }
+            _ => {
+                let high = self.read_cycle(self.cycle_state.base.wrapping_add(1)) as u16;
+                self.registers.pc = (high << 8) | self.cycle_state.address;
+                self.cycle_state.finish();
+            }
+        }
+    }
+
+    // Shared by BRK and the interrupt sequences. Interrupts do not skip the byte after the
+    // opcode and push the status with the B flag clear, a reset does reads instead of pushes.
+    fn brk_cycle(&mut self) {
+        let interrupt = self.cycle_state.interrupt;
+        let pc = self.registers.pc;
+
+        match (self.cycle_state.cycle, interrupt) {
+            (1, None) => {
+                self.fetch_operand();
+            }
+            (1, Some(_)) => {
+                self.read_cycle(pc);
+            }
+            (2..=4, Some(Interrupt::Reset)) => {
+                self.read_cycle(0x100 + self.registers.sp as u16);
+                self.registers.sp = self.registers.sp.wrapping_sub(1);
+            }
+            (2, _) => self.push_cycle((pc >> 8) as u8),
+            (3, _) => self.push_cycle(pc as u8),
+            (4, None) => self.push_cycle(self.registers.status.to_stack(true)),
+            (4, Some(_)) => self.push_cycle(self.registers.status.to_stack(false)),
+            (5, _) => {
+                // An NMI detected during the pushes hijacks the vector of BRK and IRQ
+                if self.cycle_state.nmi && matches!(interrupt, None | Some(Interrupt::Irq)) {
+                    self.acknowledge(Interrupt::Nmi);
+                    self.cycle_state.interrupt = Some(Interrupt::Nmi);
+                }
+
+                let vector = self.cycle_state.interrupt.map_or(0xFFFE, Interrupt::vector);
+                self.cycle_state.address = self.read_cycle(vector) as u16;
+                self.enter_handler();
+            }
+            _ => {
+                let vector = self.cycle_state.interrupt.map_or(0xFFFE, Interrupt::vector);
+                let high = self.read_cycle(vector + 1) as u16;
+                self.registers.pc = (high << 8) | self.cycle_state.address;
+                self.cycle_state.finish();
+            }
+        }
+    }
+
+    // BBR and BBS read the zero page value twice before fetching the offset, then branch like
+    // the other branches without their interrupt polling quirk
+    fn bit_branch_cycle(&mut self, instruction: &Instruction) {
+        let pc = self.registers.pc;
+
+        match self.cycle_state.cycle {
+            1 => {
+                self.cycle_state.pointer = self.fetch_operand();
+            }
+            2 => {
+                self.cycle_state.value = self.read_cycle(self.cycle_state.pointer as u16);
+            }
+            3 => {
+                self.read_cycle(self.cycle_state.pointer as u16);
+            }
+            4 => {
+                let offset = self.fetch_operand() as i8 as u16;
+                self.cycle_state.address = pc.wrapping_add(1).wrapping_add(offset);
+                if !self.bit_branch_condition(instruction, self.cycle_state.value) {
+                    self.cycle_state.finish();
+                }
+            }
+            5 => {
+                self.read_cycle(pc);
+                if (pc & 0xFF00) == (self.cycle_state.address & 0xFF00) {
+                    self.registers.pc = self.cycle_state.address;
+                    self.cycle_state.finish();
+                }
+            }
+            _ => {
+                self.read_cycle(pc);
+                self.registers.pc = self.cycle_state.address;
+                self.cycle_state.finish();
+            }
+        }
+    }
+
+    // A taken branch takes an extra cycle, and another one to fix the page when it crosses one.
+    // Only the page fixing cycle polls again, so taking a branch within a page delays interrupts
+    // by an instruction.
+    fn branch_cycle(&mut self, instruction: &Instruction) {
+        let pc = self.registers.pc;
+
+        match self.cycle_state.cycle {
+            1 => {
+                self.cycle_state.pointer = self.fetch_operand();
+                self.poll_cycle(self.registers.status.interrupt);
+                if !self.branch_condition(instruction) {
+                    self.cycle_state.finish();
+                }
+            }
+            2 => {
+                self.read_cycle(pc);
+                let offset = self.cycle_state.pointer as i8 as u16;
+                let target = pc.wrapping_add(offset);
+                if (pc & 0xFF00) == (target & 0xFF00) {
+                    self.registers.pc = target;
+                    self.cycle_state.finish();
+                } else {
+                    self.registers.pc = (pc & 0xFF00) | (target & 0x00FF);
+                    self.cycle_state.address = target;
+                }
+            }
+            _ => {
+                self.read_cycle(pc);
+                self.registers.pc = self.cycle_state.address;
+                self.poll_cycle(self.registers.status.interrupt);
+                self.cycle_state.finish();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use crate::variant::Wdc65C02;
+
+    fn trace(cpu: &mut CPU) -> Vec<(BusAccess, u16, u8)> {
+        let mut bus_cycles = vec![];
+        loop {
+            let bus = cpu.tick().unwrap();
+            bus_cycles.push((bus.access, bus.address, bus.value));
+            if !cpu.cycle_state.in_progress() {
+                return bus_cycles;
+            }
+        }
+    }
+
+    #[test]
+    pub fn read_modify_write_double_write() {
+        let mut cpu = CPU::default();
+        // INC $1234
+        cpu.memory.poke_bytes(0x200, &[0xEE, 0x34, 0x12]);
+        cpu.memory.poke(0x1234, 0x41);
+        cpu.registers.pc = 0x200;
+
+        assert_eq!(
+            trace(&mut cpu),
+            vec![
+                (BusAccess::Read, 0x200, 0xEE),
+                (BusAccess::Read, 0x201, 0x34),
+                (BusAccess::Read, 0x202, 0x12),
+                (BusAccess::Read, 0x1234, 0x41),
+                (BusAccess::Write, 0x1234, 0x41),
+                (BusAccess::Write, 0x1234, 0x42),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn indexed_dummy_read() {
+        let mut cpu = CPU::default();
+        // LDA $12F0,X ; STA $12F0,X
+        cpu.memory
+            .poke_bytes(0x200, &[0xBD, 0xF0, 0x12, 0x9D, 0xF0, 0x12]);
+        cpu.memory.poke(0x1300, 0x55);
+        cpu.registers.pc = 0x200;
+        cpu.registers.x = 0x10;
+
+        assert_eq!(
+            trace(&mut cpu)[3..],
+            [
+                (BusAccess::Read, 0x1200, 0x00),
+                (BusAccess::Read, 0x1300, 0x55)
+            ]
+        );
+        assert_eq!(
+            trace(&mut cpu)[3..],
+            [
+                (BusAccess::Read, 0x1200, 0x00),
+                (BusAccess::Write, 0x1300, 0x55)
+            ]
+        );
+    }
+
+    #[test]
+    pub fn wdc_65c02_read_modify_write() {
+        let mut cpu = CPU::with_variant(Memory::default(), Wdc65C02);
+        // ASL $1200,X ; ASL $12FF,X
+        cpu.memory
+            .poke_bytes(0x200, &[0x1E, 0x00, 0x12, 0x1E, 0xFF, 0x12]);
+        cpu.memory.poke_bytes(0x1201, &[0x41]);
+        cpu.memory.poke_bytes(0x1300, &[0x41]);
+        cpu.registers.pc = 0x200;
+        cpu.registers.x = 0x01;
+
+        assert_eq!(
+            trace(&mut cpu),
+            vec![
+                (BusAccess::Read, 0x200, 0x1E),
+                (BusAccess::Read, 0x201, 0x00),
+                (BusAccess::Read, 0x202, 0x12),
+                (BusAccess::Read, 0x1201, 0x41),
+                (BusAccess::Read, 0x1201, 0x41),
+                (BusAccess::Write, 0x1201, 0x82),
+            ]
+        );
+        assert_eq!(
+            trace(&mut cpu),
+            vec![
+                (BusAccess::Read, 0x203, 0x1E),
+                (BusAccess::Read, 0x204, 0xFF),
+                (BusAccess::Read, 0x205, 0x12),
+                (BusAccess::Read, 0x205, 0x12),
+                (BusAccess::Read, 0x1300, 0x41),
+                (BusAccess::Read, 0x1300, 0x41),
+                (BusAccess::Write, 0x1300, 0x82),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn step_finishes_instruction() {
+        let mut cpu = CPU::default();
+        // LDA #$10 ; NOP
+        cpu.memory.poke_bytes(0x200, &[0xA9, 0x10, 0xEA]);
+        cpu.registers.pc = 0x200;
+
+        cpu.tick().unwrap();
+        assert_eq!(cpu.step().unwrap().cycles, 1);
+        assert_eq!(cpu.registers.accumulator, 0x10);
+        assert_eq!(cpu.registers.pc, 0x202);
+    }
+
+    #[test]
+    pub fn stolen_cycles_wait_for_read() {
+        let mut cpu = CPU::default();
+        // INC $1234 ; NOP
+        cpu.memory.poke_bytes(0x200, &[0xEE, 0x34, 0x12, 0xEA]);
+        cpu.memory.poke(0x1234, 0x41);
+        cpu.registers.pc = 0x200;
+
+        for _ in 0..4 {
+            cpu.tick().unwrap();
+        }
+        cpu.steal_cycles(2);
+
+        let bus_cycles: Vec<BusAccess> = (0..5).map(|_| cpu.tick().unwrap().access).collect();
+        assert_eq!(
+            bus_cycles,
+            vec![
+                BusAccess::Write,
+                BusAccess::Write,
+                BusAccess::Stall,
+                BusAccess::Stall,
+                BusAccess::Read,
+            ]
+        );
+        assert_eq!(cpu.cycles, 9);
+        assert_eq!(cpu.registers.pc, 0x204);
+    }
+
+    #[test]
+    pub fn jam_holds_bus() {
+        let mut cpu = CPU::default();
+        cpu.memory.poke_bytes(0x200, &[0x02]);
+        cpu.registers.pc = 0x200;
+
+        trace(&mut cpu);
+        assert_eq!(cpu.state, State::Jammed);
+        assert_eq!(cpu.tick().unwrap().address, 0xFFFF);
+        assert_eq!(cpu.tick().unwrap().address, 0xFFFF);
+    }
+
+    #[test]
+    pub fn wait_for_interrupt() {
+        let mut cpu = CPU::with_variant(Memory::default(), Wdc65C02);
+        // WAI
+        cpu.memory.poke_bytes(0x200, &[0xCB]);
+        cpu.memory.poke_bytes(0xFFFA, &[0x00, 0x03]);
+        cpu.registers.pc = 0x200;
+        cpu.registers.sp = 0xFF;
+
+        assert_eq!(trace(&mut cpu).len(), 3);
+        assert_eq!(cpu.state, State::Waiting);
+        assert_eq!(cpu.tick().unwrap().access, BusAccess::Stall);
+        assert_eq!(cpu.tick().unwrap().access, BusAccess::Stall);
+
+        cpu.set_nmi(true);
+        assert_eq!(trace(&mut cpu).len(), 7);
+        assert_eq!(cpu.state, State::Running);
+        assert_eq!(cpu.registers.pc, 0x300);
+    }
+
+    #[test]
+    pub fn irq_sequence() {
+        let mut cpu = CPU::default();
+        cpu.memory.poke_bytes(0x200, &[0xEA]);
+        cpu.memory.poke_bytes(0xFFFE, &[0x00, 0x05]);
+        cpu.registers.pc = 0x200;
+        cpu.registers.sp = 0xFF;
+        cpu.registers.status = Status::from_binary(0x00);
+        cpu.set_irq(true);
+
+        assert_eq!(
+            trace(&mut cpu),
+            vec![
+                (BusAccess::Read, 0x200, 0xEA),
+                (BusAccess::Read, 0x200, 0xEA),
+                (BusAccess::Write, 0x1FF, 0x02),
+                (BusAccess::Write, 0x1FE, 0x00),
+                (BusAccess::Write, 0x1FD, 0x20),
+                (BusAccess::Read, 0xFFFE, 0x00),
+                (BusAccess::Read, 0xFFFF, 0x05),
+            ]
+        );
+        assert_eq!(cpu.registers.pc, 0x500);
+    }
+
+    #[test]
+    pub fn interrupt_flag_latency() {
+        let mut cpu = CPU::default();
+        // NOP ; SEI ; NOP
+        cpu.memory.poke_bytes(0x200, &[0xEA, 0x78, 0xEA]);
+        cpu.memory.poke_bytes(0xFFFE, &[0x00, 0x05]);
+        cpu.registers.pc = 0x200;
+        cpu.registers.status = Status::from_binary(0x00);
+
+        trace(&mut cpu);
+        cpu.set_irq(true);
+        trace(&mut cpu);
+        assert!(cpu.registers.status.interrupt);
+
+        // SEI polled with the interrupt flag still clear
+        assert_eq!(trace(&mut cpu).len(), 7);
+        assert_eq!(cpu.registers.pc, 0x500);
+    }
+
+    #[test]
+    pub fn branch_delay() {
+        let mut cpu = CPU::default();
+        // BNE to a NOP, once within the page and once crossing it
+        for (pc, offset, next) in [(0x200, 0x00, 0x202), (0x300, 0xFD, 0x2FF)] {
+            cpu.memory.poke_bytes(pc, &[0xD0, offset]);
+            cpu.memory.poke_bytes(next, &[0xEA]);
+            cpu.memory.poke_bytes(0xFFFE, &[0x00, 0x05]);
+            cpu.registers.pc = pc;
+            cpu.registers.status = Status::from_binary(0x00);
+            cpu.set_irq(false);
+
+            // The line changes after the operand fetch
+            cpu.tick().unwrap();
+            cpu.tick().unwrap();
+            cpu.set_irq(true);
+            let cycles = trace(&mut cpu).len();
+
+            if cycles == 1 {
+                // The fetch was delayed to after the next instruction
+                assert_eq!(trace(&mut cpu).len(), 2);
+                assert_eq!(cpu.registers.pc, next + 1);
+            }
+            assert_eq!(trace(&mut cpu).len(), 7);
+            assert_eq!(cpu.registers.pc, 0x500);
+        }
+    }
+
+    #[test]
+    pub fn nmi_hijacks_brk() {
+        let mut cpu = CPU::default();
+        cpu.memory.poke_bytes(0x200, &[0x00]);
+        cpu.memory
+            .poke_bytes(0xFFFA, &[0x00, 0x03, 0x00, 0x04, 0x00, 0x05]);
+        cpu.registers.pc = 0x200;
+        cpu.registers.sp = 0xFF;
+        cpu.registers.status = Status::from_binary(0x00);
+
+        // The NMI arrives while the return address is pushed
+        cpu.tick().unwrap();
+        cpu.tick().unwrap();
+        cpu.tick().unwrap();
+        cpu.set_nmi(true);
+        let bus_cycles = trace(&mut cpu);
+
+        assert_eq!(bus_cycles[2], (BusAccess::Read, 0xFFFA, 0x00));
+        assert_eq!(cpu.registers.pc, 0x300);
+        assert_eq!(cpu.memory.peek(0x1FD) & 0x10, 0x10);
+        assert!(!cpu.nmi_pending);
+    }
+
+    #[test]
+    pub fn reset_sequence() {
+        let mut cpu = CPU::default();
+        cpu.memory.poke_bytes(0xFFFC, &[0x00, 0x04]);
+        cpu.registers.sp = 0x00;
+        cpu.reset();
+
+        let bus_cycles = trace(&mut cpu);
+        assert_eq!(bus_cycles.len(), 7);
+        assert!(bus_cycles
+            .iter()
+            .all(|(access, ..)| *access == BusAccess::Read));
+        assert_eq!(cpu.registers.pc, 0x400);
+        assert_eq!(cpu.registers.sp, 0xFD);
+    }
+}
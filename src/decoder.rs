@@ -0,0 +1,318 @@
+use std::ops::RangeInclusive;
+
+use crate::bus::Bus;
+use crate::memory::{Fault, FaultKind, Memory, MemoryError};
+
+type ReadHandler = Box<dyn FnMut(u16) -> u8>;
+type WriteHandler = Box<dyn FnMut(u16, u8)>;
+
+// What the memory behind a range of addresses allows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Ram,
+    Rom,       // writes are ignored
+    Unmapped,  // nothing answers, reads see the value left on the data bus
+    NoExecute, // RAM that code must not run from
+}
+
+struct Region {
+    range: RangeInclusive<u16>,
+    read: Option<ReadHandler>,
+    write: Option<WriteHandler>,
+}
+
+// Routes accesses to the handlers registered for an address range, and to the memory behind it,
+// plain RAM or a `Mapper`, for the addresses no handler claims. Handlers get the full address,
+// and regions registered later take precedence over earlier ones where they overlap.
+//
+// Access permissions are checked before the handlers and default to RAM. In strict mode the
+// first forbidden access is kept as a fault until `take_fault`, the access itself behaves the
+// same either way.
+pub struct AddressDecoder<M: Bus = Memory> {
+    pub memory: M,
+    pub strict: bool,
+    regions: Vec<Region>,
+    accesses: Vec<(RangeInclusive<u16>, Access)>,
+    fault: Option<Fault>,
+    pc: u16,      // address of the last opcode fetch
+    data_bus: u8, // last value read or written
+}
+
+impl Default for AddressDecoder {
+    fn default() -> Self {
+        AddressDecoder::new(Memory::default())
+    }
+}
+
+impl<M: Bus> AddressDecoder<M> {
+    pub fn new(memory: M) -> Self {
+        AddressDecoder {
+            memory,
+            strict: false,
+            regions: Vec::new(),
+            accesses: Vec::new(),
+            fault: None,
+            pc: 0,
+            data_bus: 0,
+        }
+    }
+
+    // Later calls take precedence where ranges overlap
+    pub fn set_access(&mut self, range: RangeInclusive<u16>, access: Access) {
+        self.accesses.push((range, access));
+    }
+
+    pub fn access(&self, address: u16) -> Access {
+        self.accesses
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&address))
+            .map_or(Access::Ram, |(_, access)| *access)
+    }
+
+    pub fn take_fault(&mut self) -> Option<Fault> {
+        self.fault.take()
+    }
+
+    fn report(&mut self, kind: FaultKind, address: u16) {
+        if self.strict && self.fault.is_none() {
+            self.fault = Some(Fault {
+                kind,
+                pc: self.pc,
+                address,
+            });
+        }
+    }
+
+    pub fn map(
+        &mut self,
+        range: RangeInclusive<u16>,
+        read: impl FnMut(u16) -> u8 + 'static,
+        write: impl FnMut(u16, u8) + 'static,
+    ) {
+        self.regions.push(Region {
+            range,
+            read: Some(Box::new(read)),
+            write: Some(Box::new(write)),
+        });
+    }
+
+    // Reads of the range go to the handler, writes still go to RAM
+    pub fn map_read(&mut self, range: RangeInclusive<u16>, read: impl FnMut(u16) -> u8 + 'static) {
+        self.regions.push(Region {
+            range,
+            read: Some(Box::new(read)),
+            write: None,
+        });
+    }
+
+    // Writes to the range go to the handler, reads still come from RAM
+    pub fn map_write(&mut self, range: RangeInclusive<u16>, write: impl FnMut(u16, u8) + 'static) {
+        self.regions.push(Region {
+            range,
+            read: None,
+            write: Some(Box::new(write)),
+        });
+    }
+
+    // Unlike `Memory::get_byte` these never fail, forbidden accesses only become faults
+    pub fn peek(&mut self, address: u16) -> u8 {
+        if self.access(address) == Access::Unmapped {
+            self.report(FaultKind::UnmappedRead, address);
+            return self.data_bus;
+        }
+
+        self.data_bus = self.read_mapped(address);
+        self.data_bus
+    }
+
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.data_bus = value;
+        match self.access(address) {
+            Access::Rom => self.report(FaultKind::RomWrite, address),
+            Access::Unmapped => self.report(FaultKind::UnmappedWrite, address),
+            _ => self.write_mapped(address, value),
+        }
+    }
+
+    pub fn fetch_byte(&mut self, address: u16) -> u8 {
+        self.pc = address;
+        if self.access(address) == Access::NoExecute {
+            self.report(FaultKind::Execute, address);
+        }
+        self.peek(address)
+    }
+
+    fn read_mapped(&mut self, address: u16) -> u8 {
+        let handler = self
+            .regions
+            .iter_mut()
+            .rev()
+            .filter(|region| region.range.contains(&address))
+            .find_map(|region| region.read.as_mut());
+
+        match handler {
+            Some(read) => read(address),
+            None => self.memory.read(address),
+        }
+    }
+
+    fn write_mapped(&mut self, address: u16, value: u8) {
+        let handler = self
+            .regions
+            .iter_mut()
+            .rev()
+            .filter(|region| region.range.contains(&address))
+            .find_map(|region| region.write.as_mut());
+
+        match handler {
+            Some(write) => write(address, value),
+            None => self.memory.write(address, value),
+        }
+    }
+}
+
+impl<M: Bus> Bus for AddressDecoder<M> {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.poke(address, value);
+    }
+
+    fn fetch(&mut self, address: u16) -> u8 {
+        self.fetch_byte(address)
+    }
+
+    fn take_error(&mut self) -> Option<MemoryError> {
+        match self.take_fault() {
+            Some(fault) => Some(MemoryError::Fault(fault)),
+            None => self.memory.take_error(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{CpuError, StopReason, CPU};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    #[test]
+    pub fn unclaimed_addresses() {
+        let mut decoder = AddressDecoder::default();
+        decoder.map_read(0xD000..=0xD3FF, |address| (address >> 8) as u8);
+
+        decoder.poke(0xD010, 0x55);
+        assert_eq!(decoder.peek(0xD010), 0xD0);
+        assert_eq!(decoder.memory.peek(0xD010), 0x55);
+
+        decoder.poke(0xD400, 0x66);
+        assert_eq!(decoder.peek(0xD400), 0x66);
+    }
+
+    #[test]
+    pub fn later_regions_take_precedence() {
+        let mut decoder = AddressDecoder::default();
+        decoder.map_read(0xD000..=0xDFFF, |_| 0x01);
+        decoder.map_read(0xD400..=0xD4FF, |_| 0x02);
+
+        assert_eq!(decoder.peek(0xD3FF), 0x01);
+        assert_eq!(decoder.peek(0xD400), 0x02);
+        assert_eq!(decoder.peek(0xD500), 0x01);
+    }
+
+    #[test]
+    pub fn serial_and_exit_registers() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let exit = Rc::new(Cell::new(None));
+
+        let mut decoder = AddressDecoder::default();
+        let serial = output.clone();
+        decoder.map_write(0xF001..=0xF001, move |_, value| {
+            serial.borrow_mut().push(value)
+        });
+        // Pointer to the message, read through the decoder by the indirect addressing
+        decoder.map_read(0x10..=0x11, |address| [0x00, 0x03][address as usize - 0x10]);
+
+        let program = [
+            0xA0, 0x00, // LDY #$00
+            0xB1, 0x10, // LDA ($10),Y
+            0xF0, 0x06, // BEQ done
+            0x8D, 0x01, 0xF0, // STA $F001
+            0xC8, // INY
+            0xD0, 0xF6, // BNE loop
+            0x8D, 0x02, 0xF0, // done: STA $F002
+        ];
+        decoder.memory.poke_bytes(0x200, &program);
+        decoder.memory.poke_bytes(0x300, b"hi\0");
+
+        let mut cpu = CPU::new(decoder);
+        let (status, stop) = (exit.clone(), cpu.stop_handle());
+        cpu.memory.map_write(0xF002..=0xF002, move |_, value| {
+            status.set(Some(value));
+            stop.stop();
+        });
+        cpu.registers.pc = 0x200;
+
+        assert_eq!(cpu.run(), StopReason::HostRequest);
+
+        assert_eq!(output.borrow().as_slice(), b"hi");
+        assert_eq!(exit.get(), Some(0));
+    }
+
+    #[test]
+    pub fn rom_and_unmapped() {
+        let mut decoder = AddressDecoder::default();
+        decoder.memory.poke_bytes(0xE000, &[0x12]);
+        decoder.set_access(0xE000..=0xFFFF, Access::Rom);
+        decoder.set_access(0x8000..=0xDFFF, Access::Unmapped);
+
+        decoder.poke(0xE000, 0x34);
+        assert_eq!(decoder.peek(0xE000), 0x12);
+
+        // Nothing drives the data bus, so the last value read stays on it
+        decoder.poke(0x8000, 0x56);
+        assert_eq!(decoder.peek(0x8000), 0x56);
+        assert_eq!(decoder.memory.peek(0x8000), 0x00);
+        assert_eq!(decoder.take_fault(), None);
+    }
+
+    #[test]
+    pub fn strict_faults() {
+        let mut decoder = AddressDecoder::new(Memory::default());
+        decoder.strict = true;
+        decoder.set_access(0xE000..=0xFFFF, Access::Rom);
+        decoder.set_access(0x0100..=0x01FF, Access::NoExecute);
+
+        let program = [
+            0xA9, 0x01, // LDA #$01
+            0x8D, 0x00, 0xE0, // STA $E000
+            0x4C, 0x00, 0x01, // JMP $0100
+        ];
+        decoder.memory.poke_bytes(0x200, &program);
+
+        let mut cpu = CPU::new(decoder);
+        cpu.registers.pc = 0x200;
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        let fault = Fault {
+            kind: FaultKind::RomWrite,
+            pc: 0x202,
+            address: 0xE000,
+        };
+        let error = MemoryError::Fault(fault);
+        assert_eq!(cpu.step(), Err(CpuError::Memory { pc: 0x202, error }));
+        assert_eq!(cpu.memory.memory.peek(0xE000), 0x00);
+
+        assert_eq!(cpu.step().unwrap().cycles, 3);
+        let fault = Fault {
+            kind: FaultKind::Execute,
+            pc: 0x100,
+            address: 0x100,
+        };
+        let error = MemoryError::Fault(fault);
+        assert_eq!(cpu.step(), Err(CpuError::Memory { pc: 0x100, error }));
+    }
+}
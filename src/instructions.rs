@@ -1,5 +1,5 @@
 use crate::{
-    memory::Memory,
+    bus::Bus,
     registers::{Registers, Status},
 };
 
@@ -11,13 +11,31 @@ pub enum AddressingMode {
     Absolute,
     AbsoluteX,
     AbsoluteY,
+    // JMP ($xxFF) of the NMOS 6502 reads its high byte from $xx00
     Indirect,
+    // 65C02 modes, absolute indirect reads its high byte from the next page
+    AbsoluteIndirect,
+    AbsoluteIndirectX,
+    ZeroPageIndirect,
+    // Zero page address followed by a branch offset, for BBR and BBS
+    ZeroPageRelative,
     ZeroPage,
     ZeroPageX,
     ZeroPageY,
     IndirectX,
     IndirectY,
     Relative,
+    // 65816 modes, with 24-bit addresses and the relocatable stack
+    AbsoluteLong,
+    AbsoluteLongX,
+    AbsoluteIndirectLong,
+    DirectIndirectLong,
+    DirectIndirectLongY,
+    StackRelative,
+    StackRelativeIndirectY,
+    RelativeLong,
+    // Destination and source bank, for MVN and MVP
+    BlockMove,
 }
 
 impl AddressingMode {
@@ -30,50 +48,121 @@ impl AddressingMode {
             Self::AbsoluteX => 3,
             Self::AbsoluteY => 3,
             Self::Indirect => 3,
+            Self::AbsoluteIndirect => 3,
+            Self::AbsoluteIndirectX => 3,
+            Self::ZeroPageIndirect => 2,
+            Self::ZeroPageRelative => 3,
             Self::ZeroPage => 2,
             Self::ZeroPageX => 2,
             Self::ZeroPageY => 2,
             Self::IndirectX => 2,
             Self::IndirectY => 2,
             Self::Relative => 2,
+            Self::AbsoluteLong => 4,
+            Self::AbsoluteLongX => 4,
+            Self::AbsoluteIndirectLong => 3,
+            Self::DirectIndirectLong => 2,
+            Self::DirectIndirectLongY => 2,
+            Self::StackRelative => 2,
+            Self::StackRelativeIndirectY => 2,
+            Self::RelativeLong => 3,
+            Self::BlockMove => 3,
         }
     }
 
-    pub fn get_index(&self, memory: &Memory, registers: &mut Registers) -> u16 {
+    // Returns the effective address and whether indexing crossed a page boundary
+    pub fn get_index(
+        &self,
+        mut read: impl FnMut(u16) -> u8,
+        registers: &mut Registers,
+    ) -> (u16, bool) {
         let data_start = registers.pc.wrapping_add(1);
-        registers.pc += self.bytes_count();
+        registers.pc = registers.pc.wrapping_add(self.bytes_count());
 
         match self {
-            Self::Accumulator | Self::Implied => 0,
-            Self::Immediate => data_start,
-            Self::Absolute => memory.get_word(data_start),
-            Self::AbsoluteX => memory.get_word(data_start).wrapping_add(registers.x as u16),
-            Self::AbsoluteY => memory.get_word(data_start).wrapping_add(registers.y as u16),
-            Self::Indirect => memory.get_word(memory.get_word(data_start)),
-            Self::ZeroPage => memory.get_byte(data_start) as u16,
-            Self::ZeroPageX => (memory.get_byte(data_start).wrapping_add(registers.x)) as u16,
-            Self::ZeroPageY => (memory.get_byte(data_start).wrapping_add(registers.y)) as u16,
+            Self::Accumulator | Self::Implied => (0, false),
+            Self::Immediate => (data_start, false),
+            Self::Absolute => (Self::get_word(&mut read, data_start), false),
+            Self::AbsoluteX => Self::indexed(Self::get_word(&mut read, data_start), registers.x),
+            Self::AbsoluteY => Self::indexed(Self::get_word(&mut read, data_start), registers.y),
+            Self::Indirect => {
+                let pointer = Self::get_word(&mut read, data_start);
+                (Self::get_page_word(&mut read, pointer), false)
+            }
+            Self::AbsoluteIndirect => {
+                let pointer = Self::get_word(&mut read, data_start);
+                (Self::get_word(&mut read, pointer), false)
+            }
+            Self::AbsoluteIndirectX => {
+                let pointer =
+                    Self::get_word(&mut read, data_start).wrapping_add(registers.x as u16);
+                (Self::get_word(&mut read, pointer), false)
+            }
+            Self::ZeroPageIndirect => {
+                let pointer = read(data_start) as u16;
+                (Self::get_page_word(&mut read, pointer), false)
+            }
+            Self::ZeroPage | Self::ZeroPageRelative => (read(data_start) as u16, false),
+            Self::ZeroPageX => ((read(data_start).wrapping_add(registers.x)) as u16, false),
+            Self::ZeroPageY => ((read(data_start).wrapping_add(registers.y)) as u16, false),
             Self::IndirectX => {
-                memory.get_word((memory.get_byte(data_start).wrapping_add(registers.x)) as u16)
+                let pointer = read(data_start).wrapping_add(registers.x) as u16;
+                (Self::get_page_word(&mut read, pointer), false)
+            }
+            Self::IndirectY => {
+                let pointer = read(data_start) as u16;
+                Self::indexed(Self::get_page_word(&mut read, pointer), registers.y)
             }
-            Self::IndirectY => memory
-                .get_word(memory.get_byte(data_start) as u16)
-                .wrapping_add(registers.y as u16),
             Self::Relative => {
-                let res = memory.get_byte(data_start);
+                let res = read(data_start);
                 let sign_extend = if res & 0x80 == 0x80 { 0xffu8 } else { 0x0 };
-                u16::from_le_bytes([res, sign_extend])
+                (u16::from_le_bytes([res, sign_extend]), false)
             }
+            _ => unreachable!("{:?} is only used by the 65816", self),
         }
     }
+
+    // Operands wrap around the top of memory
+    fn get_word(read: &mut impl FnMut(u16) -> u8, index: u16) -> u16 {
+        let low = read(index) as u16;
+        (read(index.wrapping_add(1)) as u16) << 8 | low
+    }
+
+    // Pointers never carry into the next page, so JMP ($10FF) reads its high byte from $1000 and
+    // a zero page pointer at $FF from $00
+    fn get_page_word(read: &mut impl FnMut(u16) -> u8, index: u16) -> u16 {
+        let high_index = (index & 0xFF00) | (index.wrapping_add(1) & 0x00FF);
+        let low = read(index) as u16;
+        (read(high_index) as u16) << 8 | low
+    }
+
+    fn indexed(base: u16, index: u8) -> (u16, bool) {
+        let address = base.wrapping_add(index as u16);
+        (address, (base & 0xFF00) != (address & 0xFF00))
+    }
+}
+
+// How an instruction uses the bus, which determines its cycle sequence
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    // Reads the value at the effective address, an extra cycle is taken on page crossings
+    Read,
+    // Writes a register to the effective address
+    Write,
+    // Reads the effective address, writes the value back unmodified and then writes the result
+    ReadModifyWrite,
+    // Only works on registers
+    Implied,
+    // Branches, jumps, stack and interrupt instructions with their own sequences
+    Control,
 }
 
 macro_rules! addressing_instructions {
     ($($instruction:ident),*) => {
-        #[derive(Debug)]
+        #[derive(Clone, Copy, Debug, PartialEq)]
         pub enum Instruction {
             $(
-                $instruction(AddressingMode),
+                $instruction(AddressingMode, u8),
             )*
         }
 
@@ -81,7 +170,16 @@ macro_rules! addressing_instructions {
             pub fn addressing_mode(&self) -> &AddressingMode {
                 match self {
                     $(
-                        Instruction::$instruction(mode) => mode,
+                        Instruction::$instruction(mode, _) => mode,
+                    )*
+                }
+            }
+
+            // Base cycles, without page crossing or branch penalties
+            pub fn cycles(&self) -> u8 {
+                match self {
+                    $(
+                        Instruction::$instruction(_, cycles) => *cycles,
                     )*
                 }
             }
@@ -89,10 +187,23 @@ macro_rules! addressing_instructions {
     };
 }
 
+// Not formatted, rustfmt pulls the group comments onto the end of short lines
+#[rustfmt::skip]
 addressing_instructions!(
     ADC, AND, ASL, BCC, BCS, BEQ, BIT, BMI, BNE, BPL, BRK, BVC, BVS, CLC, CLD, CLI, CLV, CMP, CPX,
     CPY, DEC, DEX, DEY, EOR, INC, INX, INY, JMP, JSR, LDA, LDX, LDY, LSR, NOP, ORA, PHA, PHP, PLA,
-    PLP, ROL, ROR, RTI, RTS, SBC, SEC, SED, SEI, STA, STX, STY, TAX, TAY, TSX, TXA, TXS, TYA
+    PLP, ROL, ROR, RTI, RTS, SBC, SEC, SED, SEI, STA, STX, STY, TAX, TAY, TSX, TXA, TXS, TYA,
+    // Undocumented instructions
+    ALR, ANC, ANE, ARR, DCP, ISC, JAM, LAS, LAX, LXA, RLA, RRA, SAX, SBX, SHA, SHX, SHY, SLO, SRE,
+    TAS, USBC,
+    // 65C02 instructions
+    BRA, PHX, PHY, PLX, PLY, STP, STZ, TRB, TSB, WAI,
+    // Rockwell and WDC bit instructions, numbered by the bit they work on
+    BBR0, BBR1, BBR2, BBR3, BBR4, BBR5, BBR6, BBR7, BBS0, BBS1, BBS2, BBS3, BBS4, BBS5, BBS6, BBS7,
+    RMB0, RMB1, RMB2, RMB3, RMB4, RMB5, RMB6, RMB7, SMB0, SMB1, SMB2, SMB3, SMB4, SMB5, SMB6, SMB7,
+    // 65816 instructions
+    BRL, COP, JML, JSL, MVN, MVP, PEA, PEI, PER, PHB, PHD, PHK, PLB, PLD, REP, RTL, SEP, TCD, TCS,
+    TDC, TSC, TXY, TYX, WDM, XBA, XCE
 );
 
 impl Instruction {
@@ -100,275 +211,818 @@ impl Instruction {
     pub fn decode(code: u8) -> Option<Self> {
         match code {
             // ADC -- Add Memory to Accumulator with Carry
-            0x69 => Some(Instruction::ADC(AddressingMode::Immediate)),
-            0x65 => Some(Instruction::ADC(AddressingMode::ZeroPage)),
-            0x75 => Some(Instruction::ADC(AddressingMode::ZeroPageX)),
-            0x6D => Some(Instruction::ADC(AddressingMode::Absolute)),
-            0x7D => Some(Instruction::ADC(AddressingMode::AbsoluteX)),
-            0x79 => Some(Instruction::ADC(AddressingMode::AbsoluteY)),
-            0x61 => Some(Instruction::ADC(AddressingMode::IndirectX)),
-            0x71 => Some(Instruction::ADC(AddressingMode::IndirectY)),
+            0x69 => Some(Instruction::ADC(AddressingMode::Immediate, 2)),
+            0x65 => Some(Instruction::ADC(AddressingMode::ZeroPage, 3)),
+            0x75 => Some(Instruction::ADC(AddressingMode::ZeroPageX, 4)),
+            0x6D => Some(Instruction::ADC(AddressingMode::Absolute, 4)),
+            0x7D => Some(Instruction::ADC(AddressingMode::AbsoluteX, 4)),
+            0x79 => Some(Instruction::ADC(AddressingMode::AbsoluteY, 4)),
+            0x61 => Some(Instruction::ADC(AddressingMode::IndirectX, 6)),
+            0x71 => Some(Instruction::ADC(AddressingMode::IndirectY, 5)),
 
             // AND -- AND Memory with Accumulator
-            0x29 => Some(Instruction::AND(AddressingMode::Immediate)),
-            0x25 => Some(Instruction::AND(AddressingMode::ZeroPage)),
-            0x35 => Some(Instruction::AND(AddressingMode::ZeroPageX)),
-            0x2D => Some(Instruction::AND(AddressingMode::Absolute)),
-            0x3D => Some(Instruction::AND(AddressingMode::AbsoluteX)),
-            0x39 => Some(Instruction::AND(AddressingMode::AbsoluteY)),
-            0x21 => Some(Instruction::AND(AddressingMode::IndirectX)),
-            0x31 => Some(Instruction::AND(AddressingMode::IndirectY)),
+            0x29 => Some(Instruction::AND(AddressingMode::Immediate, 2)),
+            0x25 => Some(Instruction::AND(AddressingMode::ZeroPage, 3)),
+            0x35 => Some(Instruction::AND(AddressingMode::ZeroPageX, 4)),
+            0x2D => Some(Instruction::AND(AddressingMode::Absolute, 4)),
+            0x3D => Some(Instruction::AND(AddressingMode::AbsoluteX, 4)),
+            0x39 => Some(Instruction::AND(AddressingMode::AbsoluteY, 4)),
+            0x21 => Some(Instruction::AND(AddressingMode::IndirectX, 6)),
+            0x31 => Some(Instruction::AND(AddressingMode::IndirectY, 5)),
 
             // ASL -- Shift Left One Bit With
-            0x0A => Some(Instruction::ASL(AddressingMode::Accumulator)),
-            0x06 => Some(Instruction::ASL(AddressingMode::ZeroPage)),
-            0x16 => Some(Instruction::ASL(AddressingMode::ZeroPageX)),
-            0x0E => Some(Instruction::ASL(AddressingMode::Absolute)),
-            0x1E => Some(Instruction::ASL(AddressingMode::AbsoluteX)),
+            0x0A => Some(Instruction::ASL(AddressingMode::Accumulator, 2)),
+            0x06 => Some(Instruction::ASL(AddressingMode::ZeroPage, 5)),
+            0x16 => Some(Instruction::ASL(AddressingMode::ZeroPageX, 6)),
+            0x0E => Some(Instruction::ASL(AddressingMode::Absolute, 6)),
+            0x1E => Some(Instruction::ASL(AddressingMode::AbsoluteX, 7)),
 
             // BCC -- Branch on Carry Clear
-            0x90 => Some(Instruction::BCC(AddressingMode::Relative)),
+            0x90 => Some(Instruction::BCC(AddressingMode::Relative, 2)),
 
             // BCS -- Branch on Carry Set
-            0xB0 => Some(Instruction::BCS(AddressingMode::Relative)),
+            0xB0 => Some(Instruction::BCS(AddressingMode::Relative, 2)),
 
             // BEQ -- Branch on Result Zero
-            0xF0 => Some(Instruction::BEQ(AddressingMode::Relative)),
+            0xF0 => Some(Instruction::BEQ(AddressingMode::Relative, 2)),
 
             // BIT -- Test Bits in Memory with Accumulator
-            0x24 => Some(Instruction::BIT(AddressingMode::ZeroPage)),
-            0x2C => Some(Instruction::BIT(AddressingMode::Absolute)),
+            0x24 => Some(Instruction::BIT(AddressingMode::ZeroPage, 3)),
+            0x2C => Some(Instruction::BIT(AddressingMode::Absolute, 4)),
 
             // BMI -- Branch on Result Minux
-            0x30 => Some(Instruction::BMI(AddressingMode::Relative)),
+            0x30 => Some(Instruction::BMI(AddressingMode::Relative, 2)),
 
             // BNE -- Branch on Result not Zero
-            0xD0 => Some(Instruction::BNE(AddressingMode::Relative)),
+            0xD0 => Some(Instruction::BNE(AddressingMode::Relative, 2)),
 
             // BPL -- Branch on Result Plus
-            0x10 => Some(Instruction::BPL(AddressingMode::Relative)),
+            0x10 => Some(Instruction::BPL(AddressingMode::Relative, 2)),
 
             // BRK -- Force Break
-            0x00 => Some(Instruction::BRK(AddressingMode::Implied)),
+            0x00 => Some(Instruction::BRK(AddressingMode::Implied, 7)),
 
             // BVC -- Branch on Overflow Clear
-            0x50 => Some(Instruction::BVC(AddressingMode::Relative)),
+            0x50 => Some(Instruction::BVC(AddressingMode::Relative, 2)),
 
             // BVS -- Branch on Overflow Set
-            0x70 => Some(Instruction::BVS(AddressingMode::Relative)),
+            0x70 => Some(Instruction::BVS(AddressingMode::Relative, 2)),
 
             // CLC -- Clear Carry Flag
-            0x18 => Some(Instruction::CLC(AddressingMode::Implied)),
+            0x18 => Some(Instruction::CLC(AddressingMode::Implied, 2)),
 
             // CLD -- Clear Decimal Mode
-            0xD8 => Some(Instruction::CLD(AddressingMode::Implied)),
+            0xD8 => Some(Instruction::CLD(AddressingMode::Implied, 2)),
 
             // CLI -- Clear Interrupt Disable Bit
-            0x58 => Some(Instruction::CLI(AddressingMode::Implied)),
+            0x58 => Some(Instruction::CLI(AddressingMode::Implied, 2)),
 
             // CLV -- Clear Overflow Flag
-            0xB8 => Some(Instruction::CLV(AddressingMode::Implied)),
+            0xB8 => Some(Instruction::CLV(AddressingMode::Implied, 2)),
 
             // CMP -- Compare Memory with Accumulator
-            0xC9 => Some(Instruction::CMP(AddressingMode::Immediate)),
-            0xC5 => Some(Instruction::CMP(AddressingMode::ZeroPage)),
-            0xD5 => Some(Instruction::CMP(AddressingMode::ZeroPageX)),
-            0xCD => Some(Instruction::CMP(AddressingMode::Absolute)),
-            0xDD => Some(Instruction::CMP(AddressingMode::AbsoluteX)),
-            0xD9 => Some(Instruction::CMP(AddressingMode::AbsoluteY)),
-            0xC1 => Some(Instruction::CMP(AddressingMode::IndirectX)),
-            0xD1 => Some(Instruction::CMP(AddressingMode::IndirectY)),
+            0xC9 => Some(Instruction::CMP(AddressingMode::Immediate, 2)),
+            0xC5 => Some(Instruction::CMP(AddressingMode::ZeroPage, 3)),
+            0xD5 => Some(Instruction::CMP(AddressingMode::ZeroPageX, 4)),
+            0xCD => Some(Instruction::CMP(AddressingMode::Absolute, 4)),
+            0xDD => Some(Instruction::CMP(AddressingMode::AbsoluteX, 4)),
+            0xD9 => Some(Instruction::CMP(AddressingMode::AbsoluteY, 4)),
+            0xC1 => Some(Instruction::CMP(AddressingMode::IndirectX, 6)),
+            0xD1 => Some(Instruction::CMP(AddressingMode::IndirectY, 5)),
 
             // CPX -- Compare Memory and Index X
-            0xE0 => Some(Instruction::CPX(AddressingMode::Immediate)),
-            0xE4 => Some(Instruction::CPX(AddressingMode::ZeroPage)),
-            0xEC => Some(Instruction::CPX(AddressingMode::Absolute)),
+            0xE0 => Some(Instruction::CPX(AddressingMode::Immediate, 2)),
+            0xE4 => Some(Instruction::CPX(AddressingMode::ZeroPage, 3)),
+            0xEC => Some(Instruction::CPX(AddressingMode::Absolute, 4)),
 
             // CPY -- Compare Memory and Index Y
-            0xC0 => Some(Instruction::CPY(AddressingMode::Immediate)),
-            0xC4 => Some(Instruction::CPY(AddressingMode::ZeroPage)),
-            0xCC => Some(Instruction::CPY(AddressingMode::Absolute)),
+            0xC0 => Some(Instruction::CPY(AddressingMode::Immediate, 2)),
+            0xC4 => Some(Instruction::CPY(AddressingMode::ZeroPage, 3)),
+            0xCC => Some(Instruction::CPY(AddressingMode::Absolute, 4)),
 
             // DEC -- Decrement Memory By One
-            0xC6 => Some(Instruction::DEC(AddressingMode::ZeroPage)),
-            0xD6 => Some(Instruction::DEC(AddressingMode::ZeroPageX)),
-            0xCE => Some(Instruction::DEC(AddressingMode::Absolute)),
-            0xDE => Some(Instruction::DEC(AddressingMode::AbsoluteX)),
+            0xC6 => Some(Instruction::DEC(AddressingMode::ZeroPage, 5)),
+            0xD6 => Some(Instruction::DEC(AddressingMode::ZeroPageX, 6)),
+            0xCE => Some(Instruction::DEC(AddressingMode::Absolute, 6)),
+            0xDE => Some(Instruction::DEC(AddressingMode::AbsoluteX, 7)),
 
             // DEX -- Decrement Index X by One
-            0xCA => Some(Instruction::DEX(AddressingMode::Implied)),
+            0xCA => Some(Instruction::DEX(AddressingMode::Implied, 2)),
 
             // DEY -- Decrement Index Y by One
-            0x88 => Some(Instruction::DEY(AddressingMode::Implied)),
+            0x88 => Some(Instruction::DEY(AddressingMode::Implied, 2)),
 
             // EOR -- Exclusive-OR Memory with Accumulator
-            0x49 => Some(Instruction::EOR(AddressingMode::Immediate)),
-            0x45 => Some(Instruction::EOR(AddressingMode::ZeroPage)),
-            0x55 => Some(Instruction::EOR(AddressingMode::ZeroPageX)),
-            0x4D => Some(Instruction::EOR(AddressingMode::Absolute)),
-            0x5D => Some(Instruction::EOR(AddressingMode::AbsoluteX)),
-            0x59 => Some(Instruction::EOR(AddressingMode::AbsoluteY)),
-            0x41 => Some(Instruction::EOR(AddressingMode::IndirectX)),
-            0x51 => Some(Instruction::EOR(AddressingMode::IndirectY)),
+            0x49 => Some(Instruction::EOR(AddressingMode::Immediate, 2)),
+            0x45 => Some(Instruction::EOR(AddressingMode::ZeroPage, 3)),
+            0x55 => Some(Instruction::EOR(AddressingMode::ZeroPageX, 4)),
+            0x4D => Some(Instruction::EOR(AddressingMode::Absolute, 4)),
+            0x5D => Some(Instruction::EOR(AddressingMode::AbsoluteX, 4)),
+            0x59 => Some(Instruction::EOR(AddressingMode::AbsoluteY, 4)),
+            0x41 => Some(Instruction::EOR(AddressingMode::IndirectX, 6)),
+            0x51 => Some(Instruction::EOR(AddressingMode::IndirectY, 5)),
 
             // INC -- Increment Memory By One
-            0xE6 => Some(Instruction::INC(AddressingMode::ZeroPage)),
-            0xF6 => Some(Instruction::INC(AddressingMode::ZeroPageX)),
-            0xEE => Some(Instruction::INC(AddressingMode::Absolute)),
-            0xFE => Some(Instruction::INC(AddressingMode::AbsoluteX)),
+            0xE6 => Some(Instruction::INC(AddressingMode::ZeroPage, 5)),
+            0xF6 => Some(Instruction::INC(AddressingMode::ZeroPageX, 6)),
+            0xEE => Some(Instruction::INC(AddressingMode::Absolute, 6)),
+            0xFE => Some(Instruction::INC(AddressingMode::AbsoluteX, 7)),
 
             // INX -- Increment Index X by One
-            0xE8 => Some(Instruction::INX(AddressingMode::Implied)),
+            0xE8 => Some(Instruction::INX(AddressingMode::Implied, 2)),
 
             // INY -- Increment Index Y by One
-            0xC8 => Some(Instruction::INY(AddressingMode::Implied)),
+            0xC8 => Some(Instruction::INY(AddressingMode::Implied, 2)),
 
             // JMP -- Jump to New Location
-            0x4C => Some(Instruction::JMP(AddressingMode::Absolute)),
-            0x6C => Some(Instruction::JMP(AddressingMode::Indirect)),
+            0x4C => Some(Instruction::JMP(AddressingMode::Absolute, 3)),
+            0x6C => Some(Instruction::JMP(AddressingMode::Indirect, 5)),
 
             // JSR -- Jump to New Location Saving Return Address
-            0x20 => Some(Instruction::JSR(AddressingMode::Absolute)),
+            0x20 => Some(Instruction::JSR(AddressingMode::Absolute, 6)),
 
             // LDA -- Load Accumulator with Memory
-            0xA9 => Some(Instruction::LDA(AddressingMode::Immediate)),
-            0xA5 => Some(Instruction::LDA(AddressingMode::ZeroPage)),
-            0xB5 => Some(Instruction::LDA(AddressingMode::ZeroPageX)),
-            0xAD => Some(Instruction::LDA(AddressingMode::Absolute)),
-            0xBD => Some(Instruction::LDA(AddressingMode::AbsoluteX)),
-            0xB9 => Some(Instruction::LDA(AddressingMode::AbsoluteY)),
-            0xA1 => Some(Instruction::LDA(AddressingMode::IndirectX)),
-            0xB1 => Some(Instruction::LDA(AddressingMode::IndirectY)),
+            0xA9 => Some(Instruction::LDA(AddressingMode::Immediate, 2)),
+            0xA5 => Some(Instruction::LDA(AddressingMode::ZeroPage, 3)),
+            0xB5 => Some(Instruction::LDA(AddressingMode::ZeroPageX, 4)),
+            0xAD => Some(Instruction::LDA(AddressingMode::Absolute, 4)),
+            0xBD => Some(Instruction::LDA(AddressingMode::AbsoluteX, 4)),
+            0xB9 => Some(Instruction::LDA(AddressingMode::AbsoluteY, 4)),
+            0xA1 => Some(Instruction::LDA(AddressingMode::IndirectX, 6)),
+            0xB1 => Some(Instruction::LDA(AddressingMode::IndirectY, 5)),
 
             // LDX -- Load Index X with Memory
-            0xA2 => Some(Instruction::LDX(AddressingMode::Immediate)),
-            0xA6 => Some(Instruction::LDX(AddressingMode::ZeroPage)),
-            0xB6 => Some(Instruction::LDX(AddressingMode::ZeroPageY)),
-            0xAE => Some(Instruction::LDX(AddressingMode::Absolute)),
-            0xBE => Some(Instruction::LDX(AddressingMode::AbsoluteY)),
+            0xA2 => Some(Instruction::LDX(AddressingMode::Immediate, 2)),
+            0xA6 => Some(Instruction::LDX(AddressingMode::ZeroPage, 3)),
+            0xB6 => Some(Instruction::LDX(AddressingMode::ZeroPageY, 4)),
+            0xAE => Some(Instruction::LDX(AddressingMode::Absolute, 4)),
+            0xBE => Some(Instruction::LDX(AddressingMode::AbsoluteY, 4)),
 
             // LDY -- Load Index Y with Memory
-            0xA0 => Some(Instruction::LDY(AddressingMode::Immediate)),
-            0xA4 => Some(Instruction::LDY(AddressingMode::ZeroPage)),
-            0xB4 => Some(Instruction::LDY(AddressingMode::ZeroPageX)),
-            0xAC => Some(Instruction::LDY(AddressingMode::Absolute)),
-            0xBC => Some(Instruction::LDY(AddressingMode::AbsoluteX)),
+            0xA0 => Some(Instruction::LDY(AddressingMode::Immediate, 2)),
+            0xA4 => Some(Instruction::LDY(AddressingMode::ZeroPage, 3)),
+            0xB4 => Some(Instruction::LDY(AddressingMode::ZeroPageX, 4)),
+            0xAC => Some(Instruction::LDY(AddressingMode::Absolute, 4)),
+            0xBC => Some(Instruction::LDY(AddressingMode::AbsoluteX, 4)),
 
             // LSR -- Shift One Bit Right (Memory or Accumulator)
-            0x4A => Some(Instruction::LSR(AddressingMode::Accumulator)),
-            0x46 => Some(Instruction::LSR(AddressingMode::ZeroPage)),
-            0x56 => Some(Instruction::LSR(AddressingMode::ZeroPageX)),
-            0x4E => Some(Instruction::LSR(AddressingMode::Absolute)),
-            0x5E => Some(Instruction::LSR(AddressingMode::AbsoluteX)),
+            0x4A => Some(Instruction::LSR(AddressingMode::Accumulator, 2)),
+            0x46 => Some(Instruction::LSR(AddressingMode::ZeroPage, 5)),
+            0x56 => Some(Instruction::LSR(AddressingMode::ZeroPageX, 6)),
+            0x4E => Some(Instruction::LSR(AddressingMode::Absolute, 6)),
+            0x5E => Some(Instruction::LSR(AddressingMode::AbsoluteX, 7)),
 
             // NOP -- No Operation
-            0xEA => Some(Instruction::NOP(AddressingMode::Implied)),
+            0xEA => Some(Instruction::NOP(AddressingMode::Implied, 2)),
 
             // ORA -- OR Memory with Accumulator
-            0x09 => Some(Instruction::ORA(AddressingMode::Immediate)),
-            0x05 => Some(Instruction::ORA(AddressingMode::ZeroPage)),
-            0x15 => Some(Instruction::ORA(AddressingMode::ZeroPageX)),
-            0x0D => Some(Instruction::ORA(AddressingMode::Absolute)),
-            0x1D => Some(Instruction::ORA(AddressingMode::AbsoluteX)),
-            0x19 => Some(Instruction::ORA(AddressingMode::AbsoluteY)),
-            0x01 => Some(Instruction::ORA(AddressingMode::IndirectX)),
-            0x11 => Some(Instruction::ORA(AddressingMode::IndirectY)),
+            0x09 => Some(Instruction::ORA(AddressingMode::Immediate, 2)),
+            0x05 => Some(Instruction::ORA(AddressingMode::ZeroPage, 3)),
+            0x15 => Some(Instruction::ORA(AddressingMode::ZeroPageX, 4)),
+            0x0D => Some(Instruction::ORA(AddressingMode::Absolute, 4)),
+            0x1D => Some(Instruction::ORA(AddressingMode::AbsoluteX, 4)),
+            0x19 => Some(Instruction::ORA(AddressingMode::AbsoluteY, 4)),
+            0x01 => Some(Instruction::ORA(AddressingMode::IndirectX, 6)),
+            0x11 => Some(Instruction::ORA(AddressingMode::IndirectY, 5)),
 
             // PHA -- Push Accumulator on Stack
-            0x48 => Some(Instruction::PHA(AddressingMode::Implied)),
+            0x48 => Some(Instruction::PHA(AddressingMode::Implied, 3)),
 
             // PHP -- Push Processor Status on Stack
-            0x08 => Some(Instruction::PHP(AddressingMode::Implied)),
+            0x08 => Some(Instruction::PHP(AddressingMode::Implied, 3)),
 
             // PLA -- Pull Accumulator from Stack
-            0x68 => Some(Instruction::PLA(AddressingMode::Implied)),
+            0x68 => Some(Instruction::PLA(AddressingMode::Implied, 4)),
 
             // PLP -- Pull Processor Status from Stack
-            0x28 => Some(Instruction::PLP(AddressingMode::Implied)),
+            0x28 => Some(Instruction::PLP(AddressingMode::Implied, 4)),
 
             // ROL -- Rotate One Bit Left
-            0x2A => Some(Instruction::ROL(AddressingMode::Accumulator)),
-            0x26 => Some(Instruction::ROL(AddressingMode::ZeroPage)),
-            0x36 => Some(Instruction::ROL(AddressingMode::ZeroPageX)),
-            0x2E => Some(Instruction::ROL(AddressingMode::Absolute)),
-            0x3E => Some(Instruction::ROL(AddressingMode::AbsoluteX)),
+            0x2A => Some(Instruction::ROL(AddressingMode::Accumulator, 2)),
+            0x26 => Some(Instruction::ROL(AddressingMode::ZeroPage, 5)),
+            0x36 => Some(Instruction::ROL(AddressingMode::ZeroPageX, 6)),
+            0x2E => Some(Instruction::ROL(AddressingMode::Absolute, 6)),
+            0x3E => Some(Instruction::ROL(AddressingMode::AbsoluteX, 7)),
 
             // ROR -- Rotate One Bit Right
-            0x6A => Some(Instruction::ROR(AddressingMode::Accumulator)),
-            0x66 => Some(Instruction::ROR(AddressingMode::ZeroPage)),
-            0x76 => Some(Instruction::ROR(AddressingMode::ZeroPageX)),
-            0x6E => Some(Instruction::ROR(AddressingMode::Absolute)),
-            0x7E => Some(Instruction::ROR(AddressingMode::AbsoluteX)),
+            0x6A => Some(Instruction::ROR(AddressingMode::Accumulator, 2)),
+            0x66 => Some(Instruction::ROR(AddressingMode::ZeroPage, 5)),
+            0x76 => Some(Instruction::ROR(AddressingMode::ZeroPageX, 6)),
+            0x6E => Some(Instruction::ROR(AddressingMode::Absolute, 6)),
+            0x7E => Some(Instruction::ROR(AddressingMode::AbsoluteX, 7)),
 
             // RTI -- Return from Interrupt
-            0x40 => Some(Instruction::RTI(AddressingMode::Implied)),
+            0x40 => Some(Instruction::RTI(AddressingMode::Implied, 6)),
 
             // RTS -- Return from Subroutine
-            0x60 => Some(Instruction::RTS(AddressingMode::Implied)),
+            0x60 => Some(Instruction::RTS(AddressingMode::Implied, 6)),
 
             // SBC -- Subtract Memory from Accumulator with Borrow
-            0xE9 => Some(Instruction::SBC(AddressingMode::Immediate)),
-            0xE5 => Some(Instruction::SBC(AddressingMode::ZeroPage)),
-            0xF5 => Some(Instruction::SBC(AddressingMode::ZeroPageX)),
-            0xED => Some(Instruction::SBC(AddressingMode::Absolute)),
-            0xFD => Some(Instruction::SBC(AddressingMode::AbsoluteX)),
-            0xF9 => Some(Instruction::SBC(AddressingMode::AbsoluteY)),
-            0xE1 => Some(Instruction::SBC(AddressingMode::IndirectX)),
-            0xF1 => Some(Instruction::SBC(AddressingMode::IndirectY)),
+            0xE9 => Some(Instruction::SBC(AddressingMode::Immediate, 2)),
+            0xE5 => Some(Instruction::SBC(AddressingMode::ZeroPage, 3)),
+            0xF5 => Some(Instruction::SBC(AddressingMode::ZeroPageX, 4)),
+            0xED => Some(Instruction::SBC(AddressingMode::Absolute, 4)),
+            0xFD => Some(Instruction::SBC(AddressingMode::AbsoluteX, 4)),
+            0xF9 => Some(Instruction::SBC(AddressingMode::AbsoluteY, 4)),
+            0xE1 => Some(Instruction::SBC(AddressingMode::IndirectX, 6)),
+            0xF1 => Some(Instruction::SBC(AddressingMode::IndirectY, 5)),
 
             // SEC -- Set Carry Flag
-            0x38 => Some(Instruction::SEC(AddressingMode::Implied)),
+            0x38 => Some(Instruction::SEC(AddressingMode::Implied, 2)),
 
             // SED -- Set Decimal Flag
-            0xF8 => Some(Instruction::SED(AddressingMode::Implied)),
+            0xF8 => Some(Instruction::SED(AddressingMode::Implied, 2)),
 
             // SEI -- Set Interrupt Disable Status
-            0x78 => Some(Instruction::SEI(AddressingMode::Implied)),
+            0x78 => Some(Instruction::SEI(AddressingMode::Implied, 2)),
 
             // STA -- Subtract Memory from Accumulator with Borrow
-            0x85 => Some(Instruction::STA(AddressingMode::ZeroPage)),
-            0x95 => Some(Instruction::STA(AddressingMode::ZeroPageX)),
-            0x8D => Some(Instruction::STA(AddressingMode::Absolute)),
-            0x9D => Some(Instruction::STA(AddressingMode::AbsoluteX)),
-            0x99 => Some(Instruction::STA(AddressingMode::AbsoluteY)),
-            0x81 => Some(Instruction::STA(AddressingMode::IndirectX)),
-            0x91 => Some(Instruction::STA(AddressingMode::IndirectY)),
+            0x85 => Some(Instruction::STA(AddressingMode::ZeroPage, 3)),
+            0x95 => Some(Instruction::STA(AddressingMode::ZeroPageX, 4)),
+            0x8D => Some(Instruction::STA(AddressingMode::Absolute, 4)),
+            0x9D => Some(Instruction::STA(AddressingMode::AbsoluteX, 5)),
+            0x99 => Some(Instruction::STA(AddressingMode::AbsoluteY, 5)),
+            0x81 => Some(Instruction::STA(AddressingMode::IndirectX, 6)),
+            0x91 => Some(Instruction::STA(AddressingMode::IndirectY, 6)),
 
             // STX -- Store Index X in Memory
-            0x86 => Some(Instruction::STX(AddressingMode::ZeroPage)),
-            0x96 => Some(Instruction::STX(AddressingMode::ZeroPageY)),
-            0x8E => Some(Instruction::STX(AddressingMode::Absolute)),
+            0x86 => Some(Instruction::STX(AddressingMode::ZeroPage, 3)),
+            0x96 => Some(Instruction::STX(AddressingMode::ZeroPageY, 4)),
+            0x8E => Some(Instruction::STX(AddressingMode::Absolute, 4)),
 
             // STY -- Store Index Y in Memory
-            0x84 => Some(Instruction::STY(AddressingMode::ZeroPage)),
-            0x94 => Some(Instruction::STY(AddressingMode::ZeroPageX)),
-            0x8C => Some(Instruction::STY(AddressingMode::Absolute)),
+            0x84 => Some(Instruction::STY(AddressingMode::ZeroPage, 3)),
+            0x94 => Some(Instruction::STY(AddressingMode::ZeroPageX, 4)),
+            0x8C => Some(Instruction::STY(AddressingMode::Absolute, 4)),
 
             // TAX -- Transfer Accumulator to Index X
-            0xAA => Some(Instruction::TAX(AddressingMode::Implied)),
+            0xAA => Some(Instruction::TAX(AddressingMode::Implied, 2)),
 
             // TAY -- Transfer Accumulator to Index Y
-            0xA8 => Some(Instruction::TAY(AddressingMode::Implied)),
+            0xA8 => Some(Instruction::TAY(AddressingMode::Implied, 2)),
 
             // TSX -- Transfer Stack Pointer to Index X
-            0xBA => Some(Instruction::TSX(AddressingMode::Implied)),
+            0xBA => Some(Instruction::TSX(AddressingMode::Implied, 2)),
 
             // TXA -- Transfer Index X to Accumulator
-            0x8A => Some(Instruction::TXA(AddressingMode::Implied)),
+            0x8A => Some(Instruction::TXA(AddressingMode::Implied, 2)),
 
             // TXS -- Transfer Index X to Stack Register
-            0x9A => Some(Instruction::TXS(AddressingMode::Implied)),
+            0x9A => Some(Instruction::TXS(AddressingMode::Implied, 2)),
 
             // TYA -- Transfer Index Y to Accumulator
-            0x98 => Some(Instruction::TYA(AddressingMode::Implied)),
+            0x98 => Some(Instruction::TYA(AddressingMode::Implied, 2)),
+
+            // Undocumented instructions, from https://www.masswerk.at/nowgobang/2021/6502-illegal-opcodes
+
+            // ALR -- AND Immediate then LSR Accumulator
+            0x4B => Some(Instruction::ALR(AddressingMode::Immediate, 2)),
+
+            // ANC -- AND Immediate then Copy Negative to Carry
+            0x0B => Some(Instruction::ANC(AddressingMode::Immediate, 2)),
+            0x2B => Some(Instruction::ANC(AddressingMode::Immediate, 2)),
+
+            // ARR -- AND Immediate then ROR Accumulator
+            0x6B => Some(Instruction::ARR(AddressingMode::Immediate, 2)),
+
+            // DCP -- DEC Memory then CMP with Accumulator
+            0xC7 => Some(Instruction::DCP(AddressingMode::ZeroPage, 5)),
+            0xD7 => Some(Instruction::DCP(AddressingMode::ZeroPageX, 6)),
+            0xCF => Some(Instruction::DCP(AddressingMode::Absolute, 6)),
+            0xDF => Some(Instruction::DCP(AddressingMode::AbsoluteX, 7)),
+            0xDB => Some(Instruction::DCP(AddressingMode::AbsoluteY, 7)),
+            0xC3 => Some(Instruction::DCP(AddressingMode::IndirectX, 8)),
+            0xD3 => Some(Instruction::DCP(AddressingMode::IndirectY, 8)),
+
+            // ISC -- INC Memory then SBC from Accumulator
+            0xE7 => Some(Instruction::ISC(AddressingMode::ZeroPage, 5)),
+            0xF7 => Some(Instruction::ISC(AddressingMode::ZeroPageX, 6)),
+            0xEF => Some(Instruction::ISC(AddressingMode::Absolute, 6)),
+            0xFF => Some(Instruction::ISC(AddressingMode::AbsoluteX, 7)),
+            0xFB => Some(Instruction::ISC(AddressingMode::AbsoluteY, 7)),
+            0xE3 => Some(Instruction::ISC(AddressingMode::IndirectX, 8)),
+            0xF3 => Some(Instruction::ISC(AddressingMode::IndirectY, 8)),
+
+            // LAX -- Load Accumulator and Index X with Memory
+            0xA7 => Some(Instruction::LAX(AddressingMode::ZeroPage, 3)),
+            0xB7 => Some(Instruction::LAX(AddressingMode::ZeroPageY, 4)),
+            0xAF => Some(Instruction::LAX(AddressingMode::Absolute, 4)),
+            0xBF => Some(Instruction::LAX(AddressingMode::AbsoluteY, 4)),
+            0xA3 => Some(Instruction::LAX(AddressingMode::IndirectX, 6)),
+            0xB3 => Some(Instruction::LAX(AddressingMode::IndirectY, 5)),
+
+            // NOP -- No Operation, reading its operand
+            0x1A => Some(Instruction::NOP(AddressingMode::Implied, 2)),
+            0x3A => Some(Instruction::NOP(AddressingMode::Implied, 2)),
+            0x5A => Some(Instruction::NOP(AddressingMode::Implied, 2)),
+            0x7A => Some(Instruction::NOP(AddressingMode::Implied, 2)),
+            0xDA => Some(Instruction::NOP(AddressingMode::Implied, 2)),
+            0xFA => Some(Instruction::NOP(AddressingMode::Implied, 2)),
+            0x80 => Some(Instruction::NOP(AddressingMode::Immediate, 2)),
+            0x82 => Some(Instruction::NOP(AddressingMode::Immediate, 2)),
+            0x89 => Some(Instruction::NOP(AddressingMode::Immediate, 2)),
+            0xC2 => Some(Instruction::NOP(AddressingMode::Immediate, 2)),
+            0xE2 => Some(Instruction::NOP(AddressingMode::Immediate, 2)),
+            0x04 => Some(Instruction::NOP(AddressingMode::ZeroPage, 3)),
+            0x44 => Some(Instruction::NOP(AddressingMode::ZeroPage, 3)),
+            0x64 => Some(Instruction::NOP(AddressingMode::ZeroPage, 3)),
+            0x14 => Some(Instruction::NOP(AddressingMode::ZeroPageX, 4)),
+            0x34 => Some(Instruction::NOP(AddressingMode::ZeroPageX, 4)),
+            0x54 => Some(Instruction::NOP(AddressingMode::ZeroPageX, 4)),
+            0x74 => Some(Instruction::NOP(AddressingMode::ZeroPageX, 4)),
+            0xD4 => Some(Instruction::NOP(AddressingMode::ZeroPageX, 4)),
+            0xF4 => Some(Instruction::NOP(AddressingMode::ZeroPageX, 4)),
+            0x0C => Some(Instruction::NOP(AddressingMode::Absolute, 4)),
+            0x1C => Some(Instruction::NOP(AddressingMode::AbsoluteX, 4)),
+            0x3C => Some(Instruction::NOP(AddressingMode::AbsoluteX, 4)),
+            0x5C => Some(Instruction::NOP(AddressingMode::AbsoluteX, 4)),
+            0x7C => Some(Instruction::NOP(AddressingMode::AbsoluteX, 4)),
+            0xDC => Some(Instruction::NOP(AddressingMode::AbsoluteX, 4)),
+            0xFC => Some(Instruction::NOP(AddressingMode::AbsoluteX, 4)),
+
+            // RLA -- ROL Memory then AND with Accumulator
+            0x27 => Some(Instruction::RLA(AddressingMode::ZeroPage, 5)),
+            0x37 => Some(Instruction::RLA(AddressingMode::ZeroPageX, 6)),
+            0x2F => Some(Instruction::RLA(AddressingMode::Absolute, 6)),
+            0x3F => Some(Instruction::RLA(AddressingMode::AbsoluteX, 7)),
+            0x3B => Some(Instruction::RLA(AddressingMode::AbsoluteY, 7)),
+            0x23 => Some(Instruction::RLA(AddressingMode::IndirectX, 8)),
+            0x33 => Some(Instruction::RLA(AddressingMode::IndirectY, 8)),
+
+            // RRA -- ROR Memory then ADC to Accumulator
+            0x67 => Some(Instruction::RRA(AddressingMode::ZeroPage, 5)),
+            0x77 => Some(Instruction::RRA(AddressingMode::ZeroPageX, 6)),
+            0x6F => Some(Instruction::RRA(AddressingMode::Absolute, 6)),
+            0x7F => Some(Instruction::RRA(AddressingMode::AbsoluteX, 7)),
+            0x7B => Some(Instruction::RRA(AddressingMode::AbsoluteY, 7)),
+            0x63 => Some(Instruction::RRA(AddressingMode::IndirectX, 8)),
+            0x73 => Some(Instruction::RRA(AddressingMode::IndirectY, 8)),
+
+            // SAX -- Store Accumulator AND Index X in Memory
+            0x87 => Some(Instruction::SAX(AddressingMode::ZeroPage, 3)),
+            0x97 => Some(Instruction::SAX(AddressingMode::ZeroPageY, 4)),
+            0x8F => Some(Instruction::SAX(AddressingMode::Absolute, 4)),
+            0x83 => Some(Instruction::SAX(AddressingMode::IndirectX, 6)),
+
+            // SBX -- Index X AND Accumulator minus Immediate into Index X
+            0xCB => Some(Instruction::SBX(AddressingMode::Immediate, 2)),
+
+            // SLO -- ASL Memory then ORA with Accumulator
+            0x07 => Some(Instruction::SLO(AddressingMode::ZeroPage, 5)),
+            0x17 => Some(Instruction::SLO(AddressingMode::ZeroPageX, 6)),
+            0x0F => Some(Instruction::SLO(AddressingMode::Absolute, 6)),
+            0x1F => Some(Instruction::SLO(AddressingMode::AbsoluteX, 7)),
+            0x1B => Some(Instruction::SLO(AddressingMode::AbsoluteY, 7)),
+            0x03 => Some(Instruction::SLO(AddressingMode::IndirectX, 8)),
+            0x13 => Some(Instruction::SLO(AddressingMode::IndirectY, 8)),
+
+            // SRE -- LSR Memory then EOR with Accumulator
+            0x47 => Some(Instruction::SRE(AddressingMode::ZeroPage, 5)),
+            0x57 => Some(Instruction::SRE(AddressingMode::ZeroPageX, 6)),
+            0x4F => Some(Instruction::SRE(AddressingMode::Absolute, 6)),
+            0x5F => Some(Instruction::SRE(AddressingMode::AbsoluteX, 7)),
+            0x5B => Some(Instruction::SRE(AddressingMode::AbsoluteY, 7)),
+            0x43 => Some(Instruction::SRE(AddressingMode::IndirectX, 8)),
+            0x53 => Some(Instruction::SRE(AddressingMode::IndirectY, 8)),
+
+            // USBC -- SBC Immediate
+            0xEB => Some(Instruction::USBC(AddressingMode::Immediate, 2)),
+
+            // Unstable undocumented instructions, the magic constant and high byte AND are
+            // chip dependent
+
+            // ANE -- (Accumulator OR Magic) AND Index X AND Immediate into Accumulator
+            0x8B => Some(Instruction::ANE(AddressingMode::Immediate, 2)),
+
+            // LAS -- Memory AND Stack Pointer into Accumulator, Index X and Stack Pointer
+            0xBB => Some(Instruction::LAS(AddressingMode::AbsoluteY, 4)),
+
+            // LXA -- (Accumulator OR Magic) AND Immediate into Accumulator and Index X
+            0xAB => Some(Instruction::LXA(AddressingMode::Immediate, 2)),
+
+            // SHA -- Store Accumulator AND Index X AND High Byte plus one
+            0x9F => Some(Instruction::SHA(AddressingMode::AbsoluteY, 5)),
+            0x93 => Some(Instruction::SHA(AddressingMode::IndirectY, 6)),
+
+            // SHX -- Store Index X AND High Byte plus one
+            0x9E => Some(Instruction::SHX(AddressingMode::AbsoluteY, 5)),
+
+            // SHY -- Store Index Y AND High Byte plus one
+            0x9C => Some(Instruction::SHY(AddressingMode::AbsoluteX, 5)),
+
+            // TAS -- Accumulator AND Index X into Stack Pointer, then SHA
+            0x9B => Some(Instruction::TAS(AddressingMode::AbsoluteY, 5)),
+
+            // JAM -- Halt the CPU until it is reset
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+                Some(Instruction::JAM(AddressingMode::Implied, 2))
+            }
+        }
+    }
+
+    // opcodes from http://www.6502.org/tutorials/65c02opcodes.html, the documented NMOS ones are
+    // shared with `decode`
+    pub fn decode_65c02(code: u8) -> Option<Self> {
+        match code {
+            // (zp) addressing of the accumulator instructions
+            0x72 => Some(Instruction::ADC(AddressingMode::ZeroPageIndirect, 5)),
+            0x32 => Some(Instruction::AND(AddressingMode::ZeroPageIndirect, 5)),
+            0xD2 => Some(Instruction::CMP(AddressingMode::ZeroPageIndirect, 5)),
+            0x52 => Some(Instruction::EOR(AddressingMode::ZeroPageIndirect, 5)),
+            0xB2 => Some(Instruction::LDA(AddressingMode::ZeroPageIndirect, 5)),
+            0x12 => Some(Instruction::ORA(AddressingMode::ZeroPageIndirect, 5)),
+            0xF2 => Some(Instruction::SBC(AddressingMode::ZeroPageIndirect, 5)),
+            0x92 => Some(Instruction::STA(AddressingMode::ZeroPageIndirect, 5)),
+
+            // Indexed shifts only take a seventh cycle when crossing a page
+            0x1E => Some(Instruction::ASL(AddressingMode::AbsoluteX, 6)),
+            0x5E => Some(Instruction::LSR(AddressingMode::AbsoluteX, 6)),
+            0x3E => Some(Instruction::ROL(AddressingMode::AbsoluteX, 6)),
+            0x7E => Some(Instruction::ROR(AddressingMode::AbsoluteX, 6)),
+
+            // BIT -- Test Bits in Memory with Accumulator, immediate only sets Z
+            0x89 => Some(Instruction::BIT(AddressingMode::Immediate, 2)),
+            0x34 => Some(Instruction::BIT(AddressingMode::ZeroPageX, 4)),
+            0x3C => Some(Instruction::BIT(AddressingMode::AbsoluteX, 4)),
+
+            // BRA -- Branch Always
+            0x80 => Some(Instruction::BRA(AddressingMode::Relative, 2)),
+
+            // DEC -- Decrement Accumulator By One
+            0x3A => Some(Instruction::DEC(AddressingMode::Accumulator, 2)),
+
+            // INC -- Increment Accumulator By One
+            0x1A => Some(Instruction::INC(AddressingMode::Accumulator, 2)),
+
+            // JMP -- Jump to New Location
+            0x6C => Some(Instruction::JMP(AddressingMode::AbsoluteIndirect, 6)),
+            0x7C => Some(Instruction::JMP(AddressingMode::AbsoluteIndirectX, 6)),
+
+            // PHX -- Push Index X on Stack
+            0xDA => Some(Instruction::PHX(AddressingMode::Implied, 3)),
+
+            // PHY -- Push Index Y on Stack
+            0x5A => Some(Instruction::PHY(AddressingMode::Implied, 3)),
+
+            // PLX -- Pull Index X from Stack
+            0xFA => Some(Instruction::PLX(AddressingMode::Implied, 4)),
+
+            // PLY -- Pull Index Y from Stack
+            0x7A => Some(Instruction::PLY(AddressingMode::Implied, 4)),
+
+            // STZ -- Store Zero in Memory
+            0x64 => Some(Instruction::STZ(AddressingMode::ZeroPage, 3)),
+            0x74 => Some(Instruction::STZ(AddressingMode::ZeroPageX, 4)),
+            0x9C => Some(Instruction::STZ(AddressingMode::Absolute, 4)),
+            0x9E => Some(Instruction::STZ(AddressingMode::AbsoluteX, 5)),
+
+            // TRB -- Test and Reset Memory Bits with Accumulator
+            0x14 => Some(Instruction::TRB(AddressingMode::ZeroPage, 5)),
+            0x1C => Some(Instruction::TRB(AddressingMode::Absolute, 6)),
+
+            // TSB -- Test and Set Memory Bits with Accumulator
+            0x04 => Some(Instruction::TSB(AddressingMode::ZeroPage, 5)),
+            0x0C => Some(Instruction::TSB(AddressingMode::Absolute, 6)),
+
+            // STP -- Stop the Clock until a reset
+            0xDB => Some(Instruction::STP(AddressingMode::Implied, 3)),
+
+            // WAI -- Wait for Interrupt
+            0xCB => Some(Instruction::WAI(AddressingMode::Implied, 3)),
+
+            // BBR -- Branch on Bit Reset
+            0x0F => Some(Instruction::BBR0(AddressingMode::ZeroPageRelative, 5)),
+            0x1F => Some(Instruction::BBR1(AddressingMode::ZeroPageRelative, 5)),
+            0x2F => Some(Instruction::BBR2(AddressingMode::ZeroPageRelative, 5)),
+            0x3F => Some(Instruction::BBR3(AddressingMode::ZeroPageRelative, 5)),
+            0x4F => Some(Instruction::BBR4(AddressingMode::ZeroPageRelative, 5)),
+            0x5F => Some(Instruction::BBR5(AddressingMode::ZeroPageRelative, 5)),
+            0x6F => Some(Instruction::BBR6(AddressingMode::ZeroPageRelative, 5)),
+            0x7F => Some(Instruction::BBR7(AddressingMode::ZeroPageRelative, 5)),
+
+            // BBS -- Branch on Bit Set
+            0x8F => Some(Instruction::BBS0(AddressingMode::ZeroPageRelative, 5)),
+            0x9F => Some(Instruction::BBS1(AddressingMode::ZeroPageRelative, 5)),
+            0xAF => Some(Instruction::BBS2(AddressingMode::ZeroPageRelative, 5)),
+            0xBF => Some(Instruction::BBS3(AddressingMode::ZeroPageRelative, 5)),
+            0xCF => Some(Instruction::BBS4(AddressingMode::ZeroPageRelative, 5)),
+            0xDF => Some(Instruction::BBS5(AddressingMode::ZeroPageRelative, 5)),
+            0xEF => Some(Instruction::BBS6(AddressingMode::ZeroPageRelative, 5)),
+            0xFF => Some(Instruction::BBS7(AddressingMode::ZeroPageRelative, 5)),
+
+            // RMB -- Reset Memory Bit
+            0x07 => Some(Instruction::RMB0(AddressingMode::ZeroPage, 5)),
+            0x17 => Some(Instruction::RMB1(AddressingMode::ZeroPage, 5)),
+            0x27 => Some(Instruction::RMB2(AddressingMode::ZeroPage, 5)),
+            0x37 => Some(Instruction::RMB3(AddressingMode::ZeroPage, 5)),
+            0x47 => Some(Instruction::RMB4(AddressingMode::ZeroPage, 5)),
+            0x57 => Some(Instruction::RMB5(AddressingMode::ZeroPage, 5)),
+            0x67 => Some(Instruction::RMB6(AddressingMode::ZeroPage, 5)),
+            0x77 => Some(Instruction::RMB7(AddressingMode::ZeroPage, 5)),
+
+            // SMB -- Set Memory Bit
+            0x87 => Some(Instruction::SMB0(AddressingMode::ZeroPage, 5)),
+            0x97 => Some(Instruction::SMB1(AddressingMode::ZeroPage, 5)),
+            0xA7 => Some(Instruction::SMB2(AddressingMode::ZeroPage, 5)),
+            0xB7 => Some(Instruction::SMB3(AddressingMode::ZeroPage, 5)),
+            0xC7 => Some(Instruction::SMB4(AddressingMode::ZeroPage, 5)),
+            0xD7 => Some(Instruction::SMB5(AddressingMode::ZeroPage, 5)),
+            0xE7 => Some(Instruction::SMB6(AddressingMode::ZeroPage, 5)),
+            0xF7 => Some(Instruction::SMB7(AddressingMode::ZeroPage, 5)),
+
+            // NOP -- No Operation, the undefined opcodes skip their operands
+            0x02 | 0x22 | 0x42 | 0x62 | 0x82 | 0xC2 | 0xE2 => {
+                Some(Instruction::NOP(AddressingMode::Immediate, 2))
+            }
+            0x44 => Some(Instruction::NOP(AddressingMode::ZeroPage, 3)),
+            0x54 | 0xD4 | 0xF4 => Some(Instruction::NOP(AddressingMode::ZeroPageX, 4)),
+            0x5C => Some(Instruction::NOP(AddressingMode::Absolute, 8)),
+            0xDC | 0xFC => Some(Instruction::NOP(AddressingMode::Absolute, 4)),
+            _ if code & 0x07 == 0x03 => Some(Instruction::NOP(AddressingMode::Implied, 1)),
+
+            _ => Instruction::decode(code),
+        }
+    }
+
+    // opcodes from https://www.westerndesigncenter.com/wdc/documentation/w65c816s.pdf, the ones
+    // shared with the 65C02 come from `decode_65c02`. Cycles are for 8-bit registers in emulation
+    // mode, the 65816 core adds the cycles of 16-bit registers and a relocated direct page.
+    pub fn decode_65816(code: u8) -> Option<Self> {
+        // The accumulator instructions share their new addressing modes by column
+        let accumulator = |code: u8, mode: AddressingMode, cycles: u8| {
+            let instruction = match code >> 5 {
+                0 => Instruction::ORA(mode, cycles),
+                1 => Instruction::AND(mode, cycles),
+                2 => Instruction::EOR(mode, cycles),
+                3 => Instruction::ADC(mode, cycles),
+                4 => Instruction::STA(mode, cycles),
+                5 => Instruction::LDA(mode, cycles),
+                6 => Instruction::CMP(mode, cycles),
+                _ => Instruction::SBC(mode, cycles),
+            };
+            Some(instruction)
+        };
+
+        match code {
+            _ if code & 0x1F == 0x03 => accumulator(code, AddressingMode::StackRelative, 4),
+            _ if code & 0x1F == 0x07 => accumulator(code, AddressingMode::DirectIndirectLong, 6),
+            _ if code & 0x1F == 0x0F => accumulator(code, AddressingMode::AbsoluteLong, 5),
+            _ if code & 0x1F == 0x13 => {
+                accumulator(code, AddressingMode::StackRelativeIndirectY, 7)
+            }
+            _ if code & 0x1F == 0x17 => accumulator(code, AddressingMode::DirectIndirectLongY, 6),
+            _ if code & 0x1F == 0x1F => accumulator(code, AddressingMode::AbsoluteLongX, 5),
+
+            // BRK -- Force Break, skipping a signature byte
+            0x00 => Some(Instruction::BRK(AddressingMode::Immediate, 7)),
+
+            // COP -- Co-Processor Enable
+            0x02 => Some(Instruction::COP(AddressingMode::Immediate, 7)),
+
+            // Indexed shifts always take seven cycles
+            0x1E => Some(Instruction::ASL(AddressingMode::AbsoluteX, 7)),
+            0x5E => Some(Instruction::LSR(AddressingMode::AbsoluteX, 7)),
+            0x3E => Some(Instruction::ROL(AddressingMode::AbsoluteX, 7)),
+            0x7E => Some(Instruction::ROR(AddressingMode::AbsoluteX, 7)),
+
+            // BRL -- Branch Always Long
+            0x82 => Some(Instruction::BRL(AddressingMode::RelativeLong, 4)),
+
+            // JMP -- Jump to New Location, the pointer is read from bank 0
+            0x6C => Some(Instruction::JMP(AddressingMode::AbsoluteIndirect, 5)),
+
+            // JML -- Jump Long
+            0x5C => Some(Instruction::JML(AddressingMode::AbsoluteLong, 4)),
+            0xDC => Some(Instruction::JML(AddressingMode::AbsoluteIndirectLong, 6)),
+
+            // JSR -- Jump to New Location Saving Return Address
+            0xFC => Some(Instruction::JSR(AddressingMode::AbsoluteIndirectX, 8)),
+
+            // JSL -- Jump Long Saving Return Address
+            0x22 => Some(Instruction::JSL(AddressingMode::AbsoluteLong, 8)),
+
+            // MVN, MVP -- Block Move Negative and Positive, one byte each time they execute
+            0x54 => Some(Instruction::MVN(AddressingMode::BlockMove, 7)),
+            0x44 => Some(Instruction::MVP(AddressingMode::BlockMove, 7)),
+
+            // PEA, PEI, PER -- Push Effective Absolute, Indirect and Relative Address
+            0xF4 => Some(Instruction::PEA(AddressingMode::Absolute, 5)),
+            0xD4 => Some(Instruction::PEI(AddressingMode::ZeroPageIndirect, 6)),
+            0x62 => Some(Instruction::PER(AddressingMode::RelativeLong, 6)),
+
+            // PHB, PHD, PHK -- Push Data Bank, Direct Page and Program Bank
+            0x8B => Some(Instruction::PHB(AddressingMode::Implied, 3)),
+            0x0B => Some(Instruction::PHD(AddressingMode::Implied, 4)),
+            0x4B => Some(Instruction::PHK(AddressingMode::Implied, 3)),
+
+            // PLB, PLD -- Pull Data Bank and Direct Page
+            0xAB => Some(Instruction::PLB(AddressingMode::Implied, 4)),
+            0x2B => Some(Instruction::PLD(AddressingMode::Implied, 5)),
+
+            // REP, SEP -- Reset and Set Status Bits
+            0xC2 => Some(Instruction::REP(AddressingMode::Immediate, 3)),
+            0xE2 => Some(Instruction::SEP(AddressingMode::Immediate, 3)),
+
+            // RTL -- Return from Subroutine Long
+            0x6B => Some(Instruction::RTL(AddressingMode::Implied, 6)),
+
+            // TCD, TCS, TDC, TSC -- Transfers between C, the direct page and stack pointer
+            0x5B => Some(Instruction::TCD(AddressingMode::Implied, 2)),
+            0x1B => Some(Instruction::TCS(AddressingMode::Implied, 2)),
+            0x7B => Some(Instruction::TDC(AddressingMode::Implied, 2)),
+            0x3B => Some(Instruction::TSC(AddressingMode::Implied, 2)),
+
+            // TXY, TYX -- Transfer Index X to Y and Y to X
+            0x9B => Some(Instruction::TXY(AddressingMode::Implied, 2)),
+            0xBB => Some(Instruction::TYX(AddressingMode::Implied, 2)),
+
+            // WDM -- Reserved, skips its operand
+            0x42 => Some(Instruction::WDM(AddressingMode::Immediate, 2)),
+
+            // XBA -- Exchange B and A
+            0xEB => Some(Instruction::XBA(AddressingMode::Implied, 3)),
+
+            // XCE -- Exchange Carry and Emulation Bits
+            0xFB => Some(Instruction::XCE(AddressingMode::Implied, 2)),
+
+            _ => Instruction::decode_65c02(code),
+        }
+    }
+
+    // Instructions whose change of the interrupt disable flag is only seen after the next one
+    pub fn delays_interrupt_flag(&self) -> bool {
+        matches!(
+            self,
+            Instruction::CLI(..) | Instruction::SEI(..) | Instruction::PLP(..)
+        )
+    }
+
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self,
+            Instruction::BCC(..)
+                | Instruction::BCS(..)
+                | Instruction::BEQ(..)
+                | Instruction::BMI(..)
+                | Instruction::BNE(..)
+                | Instruction::BPL(..)
+                | Instruction::BVC(..)
+                | Instruction::BVS(..)
+                | Instruction::BRA(..)
+        )
+    }
 
-            // NOP -- NOP for all Non Documented Instructions, for now...
-            _ => None,
+    // BBR and BBS, which branch on a bit of a zero page value
+    pub fn is_bit_branch(&self) -> bool {
+        matches!(
+            self,
+            Instruction::BBR0(..)
+                | Instruction::BBR1(..)
+                | Instruction::BBR2(..)
+                | Instruction::BBR3(..)
+                | Instruction::BBR4(..)
+                | Instruction::BBR5(..)
+                | Instruction::BBR6(..)
+                | Instruction::BBR7(..)
+                | Instruction::BBS0(..)
+                | Instruction::BBS1(..)
+                | Instruction::BBS2(..)
+                | Instruction::BBS3(..)
+                | Instruction::BBS4(..)
+                | Instruction::BBS5(..)
+                | Instruction::BBS6(..)
+                | Instruction::BBS7(..)
+        )
+    }
+
+    // Bit number of the RMB, SMB, BBR and BBS instructions, and whether they test or store it set
+    pub fn zero_page_bit(&self) -> (u8, bool) {
+        match self {
+            Instruction::RMB0(..) | Instruction::BBR0(..) => (0, false),
+            Instruction::SMB0(..) | Instruction::BBS0(..) => (0, true),
+            Instruction::RMB1(..) | Instruction::BBR1(..) => (1, false),
+            Instruction::SMB1(..) | Instruction::BBS1(..) => (1, true),
+            Instruction::RMB2(..) | Instruction::BBR2(..) => (2, false),
+            Instruction::SMB2(..) | Instruction::BBS2(..) => (2, true),
+            Instruction::RMB3(..) | Instruction::BBR3(..) => (3, false),
+            Instruction::SMB3(..) | Instruction::BBS3(..) => (3, true),
+            Instruction::RMB4(..) | Instruction::BBR4(..) => (4, false),
+            Instruction::SMB4(..) | Instruction::BBS4(..) => (4, true),
+            Instruction::RMB5(..) | Instruction::BBR5(..) => (5, false),
+            Instruction::SMB5(..) | Instruction::BBS5(..) => (5, true),
+            Instruction::RMB6(..) | Instruction::BBR6(..) => (6, false),
+            Instruction::SMB6(..) | Instruction::BBS6(..) => (6, true),
+            Instruction::RMB7(..) | Instruction::BBR7(..) => (7, false),
+            Instruction::SMB7(..) | Instruction::BBS7(..) => (7, true),
+            _ => unreachable!("{:?} does not work on a zero page bit", self),
+        }
+    }
+
+    pub fn operation(&self) -> Operation {
+        match self {
+            Instruction::ADC(..)
+            | Instruction::AND(..)
+            | Instruction::BIT(..)
+            | Instruction::CMP(..)
+            | Instruction::CPX(..)
+            | Instruction::CPY(..)
+            | Instruction::EOR(..)
+            | Instruction::LDA(..)
+            | Instruction::LDX(..)
+            | Instruction::LDY(..)
+            | Instruction::ORA(..)
+            | Instruction::SBC(..)
+            | Instruction::ALR(..)
+            | Instruction::ANC(..)
+            | Instruction::ARR(..)
+            | Instruction::LAX(..)
+            | Instruction::SBX(..)
+            | Instruction::USBC(..)
+            | Instruction::ANE(..)
+            | Instruction::LAS(..)
+            | Instruction::LXA(..)
+            | Instruction::REP(..)
+            | Instruction::SEP(..)
+            | Instruction::WDM(..) => Operation::Read,
+            Instruction::NOP(mode, _) if *mode != AddressingMode::Implied => Operation::Read,
+            Instruction::STA(..)
+            | Instruction::STX(..)
+            | Instruction::STY(..)
+            | Instruction::SAX(..)
+            | Instruction::SHA(..)
+            | Instruction::SHX(..)
+            | Instruction::SHY(..)
+            | Instruction::TAS(..)
+            | Instruction::STZ(..) => Operation::Write,
+            Instruction::ASL(..)
+            | Instruction::DEC(..)
+            | Instruction::INC(..)
+            | Instruction::LSR(..)
+            | Instruction::ROL(..)
+            | Instruction::ROR(..)
+            | Instruction::DCP(..)
+            | Instruction::ISC(..)
+            | Instruction::RLA(..)
+            | Instruction::RRA(..)
+            | Instruction::SLO(..)
+            | Instruction::SRE(..)
+            | Instruction::TRB(..)
+            | Instruction::TSB(..)
+            | Instruction::RMB0(..)
+            | Instruction::RMB1(..)
+            | Instruction::RMB2(..)
+            | Instruction::RMB3(..)
+            | Instruction::RMB4(..)
+            | Instruction::RMB5(..)
+            | Instruction::RMB6(..)
+            | Instruction::RMB7(..)
+            | Instruction::SMB0(..)
+            | Instruction::SMB1(..)
+            | Instruction::SMB2(..)
+            | Instruction::SMB3(..)
+            | Instruction::SMB4(..)
+            | Instruction::SMB5(..)
+            | Instruction::SMB6(..)
+            | Instruction::SMB7(..) => Operation::ReadModifyWrite,
+            Instruction::CLC(..)
+            | Instruction::CLD(..)
+            | Instruction::CLI(..)
+            | Instruction::CLV(..)
+            | Instruction::DEX(..)
+            | Instruction::DEY(..)
+            | Instruction::INX(..)
+            | Instruction::INY(..)
+            | Instruction::NOP(..)
+            | Instruction::SEC(..)
+            | Instruction::SED(..)
+            | Instruction::SEI(..)
+            | Instruction::TAX(..)
+            | Instruction::TAY(..)
+            | Instruction::TSX(..)
+            | Instruction::TXA(..)
+            | Instruction::TXS(..)
+            | Instruction::TYA(..)
+            | Instruction::TCD(..)
+            | Instruction::TCS(..)
+            | Instruction::TDC(..)
+            | Instruction::TSC(..)
+            | Instruction::TXY(..)
+            | Instruction::TYX(..)
+            | Instruction::XBA(..)
+            | Instruction::XCE(..) => Operation::Implied,
+            _ => Operation::Control,
         }
     }
 
-    // TODO: add digit mode
     pub fn adc(accumulator: &mut u8, status: &mut Status, value: u8) {
+        if status.decimal {
+            Instruction::adc_decimal(accumulator, status, value);
+        } else {
+            Instruction::adc_binary(accumulator, status, value);
+        }
+    }
+
+    pub fn adc_binary(accumulator: &mut u8, status: &mut Status, value: u8) {
         let input_carry = status.carry as u8;
         let (nc_result, nc_carry) = accumulator.overflowing_add(value);
         let (result, carry) = nc_result.overflowing_add(input_carry);
@@ -381,12 +1035,71 @@ impl Instruction {
         *accumulator = result;
     }
 
+    // NMOS decimal addition, see http://www.6502.org/tutorials/decimal_mode.html (appendix A)
+    // Z is taken from the binary sum, N and V from the intermediate result before the high
+    // nibble is adjusted.
+    fn adc_decimal(accumulator: &mut u8, status: &mut Status, value: u8) {
+        let input_carry = status.carry as u16;
+        let (lhs, rhs) = (*accumulator as u16, value as u16);
+
+        let mut low = (lhs & 0x0F) + (rhs & 0x0F) + input_carry;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let mut result = (lhs & 0xF0) + (rhs & 0xF0) + low;
+
+        let signed = (lhs & 0xF0) as u8 as i8 as i16 + (rhs & 0xF0) as u8 as i8 as i16 + low as i16;
+        status.negative = (result & 0x80) != 0;
+        status.overflow = !(-128..=127).contains(&signed);
+        status.zero = (lhs + rhs + input_carry) as u8 == 0;
+
+        if result >= 0xA0 {
+            result += 0x60;
+        }
+        status.carry = result >= 0x100;
+        *accumulator = result as u8;
+    }
+
+    // The 65C02 sets N and Z from the decimal result, V and C are the same as on the NMOS 6502
+    pub fn adc_65c02(accumulator: &mut u8, status: &mut Status, value: u8) {
+        Instruction::adc(accumulator, status, value);
+        if status.decimal {
+            status.negative = (*accumulator & 0x80) != 0;
+            status.zero = *accumulator == 0;
+        }
+    }
+
     pub fn and(accumulator: &mut u8, status: &mut Status, value: u8) {
         *accumulator &= value;
         status.zero = *accumulator == 0;
         status.negative = (*accumulator & 0x80) != 0;
     }
 
+    // Both the binary and the NMOS decimal result, see "No More Secrets" on ARR
+    pub fn arr(accumulator: &mut u8, status: &mut Status, value: u8, decimal: bool) {
+        let operand = *accumulator & value;
+        let mut result = (operand >> 1) | ((status.carry as u8) << 7);
+
+        status.negative = status.carry;
+        status.zero = result == 0;
+        status.overflow = ((operand ^ result) & 0x40) != 0;
+
+        if !decimal {
+            status.carry = (result & 0x40) != 0;
+            *accumulator = result;
+            return;
+        }
+
+        if (operand & 0x0F) + (operand & 0x01) > 0x05 {
+            result = (result & 0xF0) | (result.wrapping_add(0x06) & 0x0F);
+        }
+        status.carry = (operand as u16 & 0xF0) + (operand as u16 & 0x10) > 0x50;
+        if status.carry {
+            result = result.wrapping_add(0x60);
+        }
+        *accumulator = result;
+    }
+
     pub fn asl(status: &mut Status, mem_value: &mut u8) {
         status.carry = (*mem_value & 0x80) != 0;
         status.negative = (*mem_value & 0x40) != 0;
@@ -394,10 +1107,20 @@ impl Instruction {
         status.zero = *mem_value == 0;
     }
 
-    pub fn branch(pc: &mut u16, condition: bool, value: u16) {
-        if condition {
-            *pc = pc.wrapping_add(value);
+    // Returns the extra cycles taken: one for a taken branch, two if it lands on another page
+    pub fn branch(pc: &mut u16, condition: bool, value: u16) -> u8 {
+        if !condition {
+            return 0;
         }
+
+        let target = pc.wrapping_add(value);
+        let extra_cycles = if (*pc & 0xFF00) != (target & 0xFF00) {
+            2
+        } else {
+            1
+        };
+        *pc = target;
+        extra_cycles
     }
 
     pub fn bit(accumulator: &mut u8, status: &mut Status, value: u8) {
@@ -406,15 +1129,21 @@ impl Instruction {
         status.negative = (value & 0x80) != 0;
     }
 
-    pub fn brk(registers: &mut Registers, memory: &mut Memory) {
+    pub fn brk(registers: &mut Registers, memory: &mut impl Bus, vector: u16) {
         registers.pc = registers.pc.wrapping_add(1);
+        let status = registers.status.to_stack(true);
+        Instruction::interrupt(registers, memory, vector, status);
+    }
+
+    // Pushes the return address and status, then jumps through the vector with interrupts disabled
+    pub fn interrupt(registers: &mut Registers, memory: &mut impl Bus, vector: u16, status: u8) {
         registers.push((registers.pc >> 8) as u8, memory);
         registers.push(registers.pc as u8, memory);
-        registers.push(registers.status.to_binary() | 0x30, memory);
+        registers.push(status, memory);
 
         registers.status.interrupt = true;
 
-        registers.pc = ((memory.get_byte(0xFFFF) as u16) << 8) | memory.get_byte(0xFFFE) as u16;
+        registers.pc = u16::from_le_bytes([memory.read(vector), memory.read(vector + 1)]);
     }
 
     pub fn compare(status: &mut Status, value_lhs: u8, value_rhs: u8) {
@@ -444,7 +1173,7 @@ impl Instruction {
         *mem_value = result;
     }
 
-    pub fn jsr(registers: &mut Registers, memory: &mut Memory, value: u16) {
+    pub fn jsr(registers: &mut Registers, memory: &mut impl Bus, value: u16) {
         registers.pc = registers.pc.wrapping_sub(1);
         registers.push((registers.pc >> 8) as u8, memory);
         registers.push(registers.pc as u8, memory);
@@ -470,13 +1199,13 @@ impl Instruction {
         status.negative = (*accumulator & 0x80) != 0;
     }
 
-    pub fn pla(registers: &mut Registers, memory: &Memory) {
+    pub fn pla(registers: &mut Registers, memory: &mut impl Bus) {
         registers.accumulator = registers.pop(memory);
         registers.status.zero = registers.accumulator == 0;
         registers.status.negative = (registers.accumulator & 0x80) != 0;
     }
 
-    pub fn plp(registers: &mut Registers, memory: &Memory) {
+    pub fn plp(registers: &mut Registers, memory: &mut impl Bus) {
         let value = registers.pop(memory);
         registers.status = Status::from_binary(value);
     }
@@ -499,18 +1228,96 @@ impl Instruction {
         status.zero = *mem_value == 0;
     }
 
-    pub fn rti(registers: &mut Registers, memory: &Memory) {
+    pub fn rti(registers: &mut Registers, memory: &mut impl Bus) {
         registers.status = Status::from_binary(registers.pop(memory));
         registers.pc = registers.pop(memory) as u16;
         registers.pc |= (registers.pop(memory) as u16) << 8;
     }
 
-    pub fn rts(registers: &mut Registers, memory: &Memory) {
+    pub fn rts(registers: &mut Registers, memory: &mut impl Bus) {
         registers.pc = registers.pop(memory) as u16;
         registers.pc |= (registers.pop(memory) as u16) << 8;
         registers.pc = registers.pc.wrapping_add(1);
     }
 
+    pub fn sbc(accumulator: &mut u8, status: &mut Status, value: u8) {
+        if !status.decimal {
+            Instruction::adc_binary(accumulator, status, !value);
+            return;
+        }
+
+        // NMOS decimal subtraction, the flags are the same as in binary mode
+        let (lhs, rhs) = (*accumulator as i16, value as i16);
+        let input_carry = status.carry as i16;
+
+        let mut low = (lhs & 0x0F) - (rhs & 0x0F) + input_carry - 1;
+        if low < 0 {
+            low = ((low - 0x06) & 0x0F) - 0x10;
+        }
+        let mut result = (lhs & 0xF0) - (rhs & 0xF0) + low;
+        if result < 0 {
+            result -= 0x60;
+        }
+
+        Instruction::adc_binary(accumulator, status, !value);
+        *accumulator = result as u8;
+    }
+
+    // 65C02 decimal subtraction adjusts the whole binary difference, see
+    // http://www.6502.org/tutorials/decimal_mode.html (appendix A). N and Z are valid.
+    pub fn sbc_65c02(accumulator: &mut u8, status: &mut Status, value: u8) {
+        if !status.decimal {
+            Instruction::adc_binary(accumulator, status, !value);
+            return;
+        }
+
+        let (lhs, rhs) = (*accumulator as i16, value as i16);
+        let input_carry = status.carry as i16;
+
+        let low = (lhs & 0x0F) - (rhs & 0x0F) + input_carry - 1;
+        let mut result = lhs - rhs + input_carry - 1;
+        if result < 0 {
+            result -= 0x60;
+        }
+        if low < 0 {
+            result -= 0x06;
+        }
+
+        Instruction::adc_binary(accumulator, status, !value);
+        *accumulator = result as u8;
+        status.negative = (*accumulator & 0x80) != 0;
+        status.zero = *accumulator == 0;
+    }
+
+    // Stores of the SH* instructions AND the value with the high byte of the base address plus
+    // one. When indexing crosses a page, that value also replaces the high byte of the address.
+    pub fn high_byte_store(value: u8, address: u16, index: u8) -> (u16, u8) {
+        let base = address.wrapping_sub(index as u16);
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+
+        if (base & 0xFF00) != (address & 0xFF00) {
+            (((value as u16) << 8) | (address & 0x00FF), value)
+        } else {
+            (address, value)
+        }
+    }
+
+    pub fn sbx(registers: &mut Registers, value: u8) {
+        let operand = registers.accumulator & registers.x;
+        Instruction::compare(&mut registers.status, operand, value);
+        registers.x = operand.wrapping_sub(value);
+    }
+
+    pub fn trb(accumulator: u8, status: &mut Status, mem_value: &mut u8) {
+        status.zero = (accumulator & *mem_value) == 0;
+        *mem_value &= !accumulator;
+    }
+
+    pub fn tsb(accumulator: u8, status: &mut Status, mem_value: &mut u8) {
+        status.zero = (accumulator & *mem_value) == 0;
+        *mem_value |= accumulator;
+    }
+
     pub fn transfer(status: &mut Status, value_lhs: u8, value_rhs: &mut u8) {
         status.zero = value_lhs == 0;
         status.negative = (value_lhs & 0x80) != 0;
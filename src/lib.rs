@@ -1,4 +1,11 @@
+pub mod bus;
 pub mod cpu;
+pub mod cycle;
+pub mod decoder;
 pub mod instructions;
+pub mod mapper;
 pub mod memory;
+pub mod port;
 pub mod registers;
+pub mod variant;
+pub mod wdc65816;
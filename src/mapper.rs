@@ -0,0 +1,313 @@
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use crate::bus::Bus;
+use crate::memory::MemoryError;
+
+pub type BankId = usize;
+pub type WindowId = usize;
+
+// Physical ROM or RAM, of any size
+struct Bank {
+    data: Vec<u8>,
+    writable: bool,
+}
+
+// A range of CPU addresses showing a bank from `offset` on. Windows larger than what is left of
+// the bank wrap around to its start, which mirrors it.
+#[derive(Clone, Debug)]
+struct Window {
+    range: RangeInclusive<u16>,
+    bank: BankId,
+    offset: usize,
+}
+
+// The windows and the bank sizes, shared with the `BankSwitch` handles
+#[derive(Default)]
+struct Layout {
+    windows: Vec<Window>,
+    bank_sizes: Vec<usize>,
+}
+
+impl Layout {
+    fn check(&self, bank: BankId, offset: usize) -> Result<(), MemoryError> {
+        match self.bank_sizes.get(bank) {
+            None => Err(MemoryError::NoSuchBank(bank)),
+            Some(&size) if offset >= size => Err(MemoryError::OutOfBounds {
+                index: offset,
+                size,
+            }),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+// Handle to switch the banks seen through the windows of a mapper, e.g. from the write handler
+// of a mapper register or the `on_write` callback of the 6510 I/O port
+#[derive(Clone)]
+pub struct BankSwitch {
+    layout: Rc<RefCell<Layout>>,
+}
+
+impl BankSwitch {
+    // Fails without changing the window for unknown ids and offsets past the end of the bank
+    pub fn select(&self, window: WindowId, bank: BankId, offset: usize) -> Result<(), MemoryError> {
+        let mut layout = self.layout.borrow_mut();
+        layout.check(bank, offset)?;
+        let window = layout
+            .windows
+            .get_mut(window)
+            .ok_or(MemoryError::NoSuchWindow(window))?;
+        window.bank = bank;
+        window.offset = offset;
+        Ok(())
+    }
+}
+
+// The 64K view of the CPU onto banks of physical memory, which can hold more than 64K. Windows
+// attached later take precedence where they overlap. Addresses outside every window read 0 and
+// ignore writes, as do writes to ROM banks.
+#[derive(Default)]
+pub struct Mapper {
+    banks: Vec<Bank>,
+    layout: Rc<RefCell<Layout>>,
+}
+
+impl Mapper {
+    pub fn new() -> Self {
+        Mapper::default()
+    }
+
+    pub fn add_ram(&mut self, size: usize) -> BankId {
+        self.add_bank(vec![0; size], true)
+    }
+
+    pub fn add_rom(&mut self, data: &[u8]) -> BankId {
+        self.add_bank(data.to_vec(), false)
+    }
+
+    // Empty banks can be added, but not attached as they have no offset to show
+    fn add_bank(&mut self, data: Vec<u8>, writable: bool) -> BankId {
+        self.layout.borrow_mut().bank_sizes.push(data.len());
+        self.banks.push(Bank { data, writable });
+        self.banks.len() - 1
+    }
+
+    // Contents of a bank, also to load RAM banks from the host
+    pub fn bank(&self, bank: BankId) -> Result<&[u8], MemoryError> {
+        self.banks
+            .get(bank)
+            .map(|bank| bank.data.as_slice())
+            .ok_or(MemoryError::NoSuchBank(bank))
+    }
+
+    pub fn bank_mut(&mut self, bank: BankId) -> Result<&mut [u8], MemoryError> {
+        self.banks
+            .get_mut(bank)
+            .map(|bank| bank.data.as_mut_slice())
+            .ok_or(MemoryError::NoSuchBank(bank))
+    }
+
+    // Fails for unknown banks and offsets past the end of the bank, like `select`
+    pub fn attach(
+        &mut self,
+        range: RangeInclusive<u16>,
+        bank: BankId,
+        offset: usize,
+    ) -> Result<WindowId, MemoryError> {
+        let mut layout = self.layout.borrow_mut();
+        layout.check(bank, offset)?;
+        layout.windows.push(Window {
+            range,
+            bank,
+            offset,
+        });
+        Ok(layout.windows.len() - 1)
+    }
+
+    pub fn select(
+        &mut self,
+        window: WindowId,
+        bank: BankId,
+        offset: usize,
+    ) -> Result<(), MemoryError> {
+        self.switch().select(window, bank, offset)
+    }
+
+    pub fn switch(&self) -> BankSwitch {
+        BankSwitch {
+            layout: self.layout.clone(),
+        }
+    }
+
+    // Bank and index within it that an address is mapped to
+    fn translate(&self, address: u16) -> Option<(BankId, usize)> {
+        self.layout
+            .borrow()
+            .windows
+            .iter()
+            .rev()
+            .find(|window| window.range.contains(&address))
+            .map(|window| {
+                let size = self.banks[window.bank].data.len();
+                let index = window.offset + (address - window.range.start()) as usize;
+                (window.bank, index % size)
+            })
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        match self.translate(address) {
+            Some((bank, index)) => self.banks[bank].data[index],
+            None => 0,
+        }
+    }
+
+    pub fn poke(&mut self, address: u16, value: u8) {
+        if let Some((bank, index)) = self.translate(address) {
+            let bank = &mut self.banks[bank];
+            if bank.writable {
+                bank.data[index] = value;
+            }
+        }
+    }
+}
+
+impl Bus for Mapper {
+    fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.poke(address, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+    use crate::decoder::AddressDecoder;
+    use crate::variant::Mos6510;
+
+    #[test]
+    pub fn mirroring() {
+        // The 2K of RAM of the NES, repeated up to $1FFF
+        let mut mapper = Mapper::new();
+        let ram = mapper.add_ram(0x800);
+        mapper.attach(0x0000..=0x1FFF, ram, 0).unwrap();
+
+        mapper.poke(0x0012, 0x34);
+        assert_eq!(mapper.peek(0x0812), 0x34);
+        assert_eq!(mapper.peek(0x1812), 0x34);
+        mapper.poke(0x1FFF, 0x56);
+        assert_eq!(mapper.peek(0x07FF), 0x56);
+        assert_eq!(mapper.peek(0x2000), 0x00);
+    }
+
+    #[test]
+    pub fn rom_banks() {
+        // 128K of program ROM in 16K banks, the last one fixed at $C000
+        let rom: Vec<u8> = (0..8).flat_map(|bank| vec![bank; 0x4000]).collect();
+        let mut mapper = Mapper::new();
+        let prg = mapper.add_rom(&rom);
+        let switchable = mapper.attach(0x8000..=0xBFFF, prg, 0).unwrap();
+        mapper.attach(0xC000..=0xFFFF, prg, 7 * 0x4000).unwrap();
+
+        assert_eq!(mapper.peek(0x8000), 0);
+        assert_eq!(mapper.peek(0xFFFF), 7);
+        mapper.poke(0xFFFF, 0x12);
+        assert_eq!(mapper.peek(0xFFFF), 7);
+
+        mapper.select(switchable, prg, 5 * 0x4000).unwrap();
+        assert_eq!(mapper.peek(0xBFFF), 5);
+        assert_eq!(mapper.bank(prg).unwrap().len(), 0x20000);
+
+        // Bad selections leave the window as it was
+        let error = MemoryError::OutOfBounds {
+            index: 0x20000,
+            size: 0x20000,
+        };
+        assert_eq!(mapper.select(switchable, prg, 0x20000), Err(error));
+        assert_eq!(
+            mapper.select(switchable, 1, 0),
+            Err(MemoryError::NoSuchBank(1))
+        );
+        assert_eq!(mapper.select(2, prg, 0), Err(MemoryError::NoSuchWindow(2)));
+        assert_eq!(mapper.peek(0xBFFF), 5);
+        // Bad windows are refused too, including any onto an empty bank
+        let empty = mapper.add_rom(&[]);
+        let error = MemoryError::OutOfBounds { index: 0, size: 0 };
+        assert_eq!(mapper.attach(0x0000..=0x7FFF, empty, 0), Err(error));
+        let error = MemoryError::NoSuchBank(2);
+        assert_eq!(mapper.attach(0x0000..=0x7FFF, 2, 0), Err(error));
+        assert_eq!(mapper.bank(2), Err(error));
+        assert_eq!(mapper.peek(0x0000), 0);
+    }
+
+    #[test]
+    pub fn mapper_register() {
+        let rom: Vec<u8> = (0..4).flat_map(|bank| vec![bank; 0x4000]).collect();
+        let mut mapper = Mapper::new();
+        let ram = mapper.add_ram(0x800);
+        let prg = mapper.add_rom(&rom);
+        mapper.attach(0x0000..=0x1FFF, ram, 0).unwrap();
+        let switchable = mapper.attach(0x8000..=0xBFFF, prg, 0).unwrap();
+        let switch = mapper.switch();
+
+        let mut decoder = AddressDecoder::new(mapper);
+        decoder.map_write(0x8000..=0xFFFF, move |_, value| {
+            switch
+                .select(switchable, prg, (value as usize & 3) * 0x4000)
+                .unwrap()
+        });
+
+        let program = [
+            0xA9, 0x02, // LDA #$02
+            0x8D, 0x00, 0x80, // STA $8000
+            0xAD, 0x00, 0x80, // LDA $8000
+        ];
+        decoder.memory.bank_mut(ram).unwrap()[0x200..0x208].copy_from_slice(&program);
+
+        let mut cpu = CPU::new(decoder);
+        cpu.registers.pc = 0x200;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.accumulator, 2);
+    }
+
+    #[test]
+    pub fn io_port_banking() {
+        // LORAM on line 0 of the 6510 port banks BASIC in and out of $A000
+        let mut mapper = Mapper::new();
+        let ram = mapper.add_ram(0x10000);
+        let basic = mapper.add_rom(&[0x94; 0x2000]);
+        mapper.attach(0x0000..=0xFFFF, ram, 0).unwrap();
+        let window = mapper.attach(0xA000..=0xBFFF, basic, 0).unwrap();
+        let switch = mapper.switch();
+
+        let program = [
+            0xA9, 0x07, // LDA #$07
+            0x85, 0x00, // STA $00
+            0xA9, 0x06, // LDA #$06
+            0x85, 0x01, // STA $01
+            0xAD, 0x00, 0xA0, // LDA $A000
+        ];
+        mapper.bank_mut(ram).unwrap()[0x200..0x20B].copy_from_slice(&program);
+        mapper.bank_mut(ram).unwrap()[0xA000] = 0x12;
+
+        let mut cpu = CPU::with_variant(mapper, Mos6510);
+        cpu.io_port()
+            .unwrap()
+            .on_write(move |lines| match lines & 1 {
+                0 => switch.select(window, ram, 0xA000).unwrap(),
+                _ => switch.select(window, basic, 0).unwrap(),
+            });
+        cpu.registers.pc = 0x200;
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.registers.accumulator, 0x12);
+    }
+}
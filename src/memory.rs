@@ -1,19 +1,63 @@
-const DEFAULT_MEM_SIZE: usize = 0x10001;
-
-// Macro for checking if index is in memory range
-macro_rules! assert_index {
-    ($index:expr, $mem_size:expr) => {
-        // Index is usize, so only check for upper out of bounds
-        assert!(
-            $index < $mem_size,
-            "[ERROR]: index {} went out of bounds!",
-            $index
-        );
-    };
+use std::error::Error;
+use std::fmt;
+
+use crate::bus::Bus;
+
+const DEFAULT_MEM_SIZE: usize = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+    RomWrite,
+    UnmappedRead,
+    UnmappedWrite,
+    Execute,
+}
+
+// An access the permissions of an `AddressDecoder` forbid, made by the instruction at `pc`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub pc: u16,
+    pub address: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    // Past the end of a memory of `size` bytes
+    OutOfBounds { index: usize, size: usize },
+    // Forbidden by the permissions of an `AddressDecoder` in strict mode
+    Fault(Fault),
+    // Ids not handed out by a `Mapper`
+    NoSuchBank(usize),
+    NoSuchWindow(usize),
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryError::OutOfBounds { index, size } => {
+                write!(
+                    f,
+                    "index {:#X} is out of bounds of {:#X} bytes",
+                    index, size
+                )
+            }
+            MemoryError::Fault(fault) => write!(
+                f,
+                "{:?} at {:#06X} by the instruction at {:#06X}",
+                fault.kind, fault.address, fault.pc
+            ),
+            MemoryError::NoSuchBank(bank) => write!(f, "bank {} does not exist", bank),
+            MemoryError::NoSuchWindow(window) => write!(f, "window {} does not exist", window),
+        }
+    }
 }
 
+impl Error for MemoryError {}
+
 pub struct Memory {
     array: Vec<u8>,
+    error: Option<MemoryError>, // first failed bus access, until `take_error`
 }
 
 impl Default for Memory {
@@ -26,37 +70,121 @@ impl Memory {
     pub fn new(size: usize) -> Self {
         Memory {
             array: vec![0; size],
+            error: None,
         }
     }
 
-    pub fn get_byte(&self, index: u16) -> u8 {
-        assert_index!(index as usize, self.array.len());
-        self.array[index as usize]
+    fn check(&self, index: usize) -> Result<usize, MemoryError> {
+        match index < self.array.len() {
+            true => Ok(index),
+            false => Err(MemoryError::OutOfBounds {
+                index,
+                size: self.array.len(),
+            }),
+        }
+    }
+
+    pub fn get_byte(&self, index: u16) -> Result<u8, MemoryError> {
+        self.check(index as usize).map(|index| self.array[index])
+    }
+
+    pub fn get_byte_mut(&mut self, index: u16) -> Result<&mut u8, MemoryError> {
+        let index = self.check(index as usize)?;
+        Ok(&mut self.array[index])
     }
 
-    pub fn get_byte_mut(&mut self, index: u16) -> &mut u8 {
-        assert_index!(index as usize, self.array.len());
-        &mut self.array[index as usize]
+    // Wraps around from $FFFF to $0000
+    pub fn get_word(&self, index: u16) -> Result<u16, MemoryError> {
+        let high = self.get_byte(index.wrapping_add(1))?;
+        Ok(((high as u16) << 8) | (self.get_byte(index)? as u16))
     }
 
-    pub fn get_word(&self, index: u16) -> u16 {
-        assert_index!(index as usize, self.array.len());
-        ((self.array[index as usize + 1] as u16) << 8) | (self.array[index as usize] as u16)
+    pub fn write_byte(&mut self, index: u16, value: u8) -> Result<(), MemoryError> {
+        *self.get_byte_mut(index)? = value;
+        Ok(())
     }
 
-    pub fn write_byte(&mut self, index: u16, value: u8) {
-        assert_index!(index as usize, self.array.len());
-        self.array[index as usize] = value;
+    pub fn write_bytes(&mut self, index: u16, values: &[u8]) -> Result<(), MemoryError> {
+        let (start, end) = (index as usize, index as usize + values.len());
+
+        // Check if both are in bounds, the end is exclusive
+        self.check(start)?;
+        self.check(end.max(1) - 1)?;
+
+        self.array[start..end].copy_from_slice(values);
+        Ok(())
+    }
+
+    // Panicking versions of the accessors above, for quick scripts and tests
+    pub fn peek(&self, index: u16) -> u8 {
+        self.get_byte(index)
+            .unwrap_or_else(|error| panic!("[ERROR]: {}!", error))
+    }
+
+    pub fn peek_word(&self, index: u16) -> u16 {
+        self.get_word(index)
+            .unwrap_or_else(|error| panic!("[ERROR]: {}!", error))
+    }
+
+    pub fn poke(&mut self, index: u16, value: u8) {
+        self.write_byte(index, value)
+            .unwrap_or_else(|error| panic!("[ERROR]: {}!", error))
+    }
+
+    pub fn poke_bytes(&mut self, index: u16, values: &[u8]) {
+        self.write_bytes(index, values)
+            .unwrap_or_else(|error| panic!("[ERROR]: {}!", error))
+    }
+
+    // Accesses to the 24-bit address space of the 65816
+    pub fn get_byte_long(&self, index: u32) -> Result<u8, MemoryError> {
+        self.check(index as usize).map(|index| self.array[index])
     }
 
-    pub fn write_bytes(&mut self, index: u16, values: &[u8]) {
+    pub fn write_byte_long(&mut self, index: u32, value: u8) -> Result<(), MemoryError> {
+        let index = self.check(index as usize)?;
+        self.array[index] = value;
+        Ok(())
+    }
+
+    pub fn write_bytes_long(&mut self, index: u32, values: &[u8]) -> Result<(), MemoryError> {
         let (start, end) = (index as usize, index as usize + values.len());
 
-        // Check if both are in bounds
-        assert_index!(start, self.array.len());
-        assert_index!(end, self.array.len());
+        self.check(start)?;
+        self.check(end.max(1) - 1)?;
 
         self.array[start..end].copy_from_slice(values);
+        Ok(())
+    }
+
+    pub fn peek_long(&self, index: u32) -> u8 {
+        self.get_byte_long(index)
+            .unwrap_or_else(|error| panic!("[ERROR]: {}!", error))
+    }
+
+    pub fn poke_bytes_long(&mut self, index: u32, values: &[u8]) {
+        self.write_bytes_long(index, values)
+            .unwrap_or_else(|error| panic!("[ERROR]: {}!", error))
+    }
+}
+
+impl Bus for Memory {
+    // Reads past the end see 0 and writes are dropped, the error is kept for `take_error`
+    fn read(&mut self, address: u16) -> u8 {
+        self.get_byte(address).unwrap_or_else(|error| {
+            self.error.get_or_insert(error);
+            0
+        })
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if let Err(error) = self.write_byte(address, value) {
+            self.error.get_or_insert(error);
+        }
+    }
+
+    fn take_error(&mut self) -> Option<MemoryError> {
+        self.error.take()
     }
 }
 
@@ -65,31 +193,88 @@ mod tests {
     use super::*;
 
     #[test]
-    #[should_panic]
     fn test_assert_get_byte() {
         let memory = Memory::new(48000);
-        memory.get_byte(50000);
+        let error = MemoryError::OutOfBounds {
+            index: 50000,
+            size: 48000,
+        };
+        assert_eq!(memory.get_byte(50000), Err(error));
+        assert_eq!(
+            memory.get_word(47999),
+            Err(MemoryError::OutOfBounds {
+                index: 48000,
+                size: 48000
+            })
+        );
     }
 
     #[test]
     #[should_panic]
+    fn test_assert_peek() {
+        let memory = Memory::new(48000);
+        memory.peek(50000);
+    }
+
+    #[test]
     fn test_assert_set_byte() {
         let mut memory = Memory::new(48000);
-        memory.write_byte(50000, 2);
+        assert!(memory.write_byte(50000, 2).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_assert_poke() {
+        let mut memory = Memory::new(48000);
+        memory.poke(50000, 2);
     }
 
     #[test]
     fn test_assert_set_bytes() {
         let mut memory = Memory::default();
         let array: Vec<u8> = vec![0; 10];
-        memory.write_bytes(50, &array);
+        assert_eq!(memory.write_bytes(50, &array), Ok(()));
+        assert_eq!(memory.write_bytes(65526, &array), Ok(()));
     }
 
     #[test]
-    #[should_panic]
     fn test_assert_set_bytes_fail() {
         let mut memory = Memory::default();
         let array: Vec<u8> = vec![0; 10];
-        memory.write_bytes(65530, &array);
+        assert!(memory.write_bytes(65530, &array).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_assert_poke_bytes() {
+        let mut memory = Memory::default();
+        let array: Vec<u8> = vec![0; 10];
+        memory.poke_bytes(65530, &array);
+    }
+
+    #[test]
+    fn test_assert_long() {
+        let mut memory = Memory::new(0x10000);
+        let error = MemoryError::OutOfBounds {
+            index: 0x12345,
+            size: 0x10000,
+        };
+        assert_eq!(memory.get_byte_long(0x12345), Err(error));
+        assert_eq!(memory.write_byte_long(0x12345, 1), Err(error));
+        assert!(memory.write_bytes_long(0xFFFF, &[1, 2]).is_err());
+        assert_eq!(memory.write_bytes_long(0xFFFE, &[1, 2]), Ok(()));
+    }
+
+    #[test]
+    fn bus_errors() {
+        let mut memory = Memory::new(48000);
+        assert_eq!(memory.read(50000), 0);
+        memory.write(50001, 2);
+        let error = MemoryError::OutOfBounds {
+            index: 50000,
+            size: 48000,
+        };
+        assert_eq!(memory.take_error(), Some(error));
+        assert_eq!(memory.take_error(), None);
     }
 }
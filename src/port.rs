@@ -0,0 +1,168 @@
+// Cycles a floating line keeps its charge, about a third of a second on a C64
+const DEFAULT_FALLOFF_CYCLES: u64 = 350_000;
+
+// Lines pulled up on the C64 board: LORAM, HIRAM, CHAREN and the cassette sense
+const DEFAULT_PULL_UPS: u8 = 0x17;
+
+// The I/O port of the 6510, with its data direction register at $0000 and data register at
+// $0001. Input lines read what drives them from the outside, then their pull-up, and otherwise
+// float: they keep the level last driven by the port until the charge fades to 0.
+pub struct IoPort {
+    direction: u8,
+    data: u8,
+    input: u8,
+    input_mask: u8,
+    pub pull_ups: u8,
+    pub falloff_cycles: u64,
+    charge: u8,
+    released: [u64; 8], // cycle at which each line stopped being driven by the port
+    on_write: Option<Box<dyn FnMut(u8)>>,
+}
+
+impl Default for IoPort {
+    fn default() -> Self {
+        IoPort::new()
+    }
+}
+
+impl IoPort {
+    pub fn new() -> Self {
+        IoPort {
+            direction: 0,
+            data: 0,
+            input: 0,
+            input_mask: 0,
+            pull_ups: DEFAULT_PULL_UPS,
+            falloff_cycles: DEFAULT_FALLOFF_CYCLES,
+            charge: 0,
+            released: [0; 8],
+            on_write: None,
+        }
+    }
+
+    pub fn is_port_address(address: u16) -> bool {
+        address < 2
+    }
+
+    // Called with the levels of the port lines after every write to $0000 or $0001 and after a
+    // reset, so a memory mapper can bank ROMs in and out
+    pub fn on_write(&mut self, callback: impl FnMut(u8) + 'static) {
+        self.on_write = Some(Box::new(callback));
+    }
+
+    // Drives the lines in `mask` from the outside, e.g. the cassette buttons
+    pub fn set_input(&mut self, value: u8, mask: u8) {
+        self.input = value & mask;
+        self.input_mask = mask;
+    }
+
+    pub fn direction(&self) -> u8 {
+        self.direction
+    }
+
+    // Levels on the port lines, as read from $0001
+    pub fn lines(&self, cycles: u64) -> u8 {
+        let floating = (0..8)
+            .filter(|bit| cycles.saturating_sub(self.released[*bit]) < self.falloff_cycles)
+            .fold(0, |charged, bit| charged | (1 << bit))
+            & self.charge;
+        let undriven = self.pull_ups | (floating & !self.pull_ups);
+        let input = self.input | (!self.input_mask & undriven);
+
+        (self.data & self.direction) | (input & !self.direction)
+    }
+
+    pub fn read(&self, address: u16, cycles: u64) -> u8 {
+        match address {
+            0 => self.direction,
+            _ => self.lines(cycles),
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8, cycles: u64) {
+        match address {
+            0 => self.set_direction(value, cycles),
+            _ => self.data = value,
+        }
+        self.notify(cycles);
+    }
+
+    // A reset turns all lines into inputs and clears the data register
+    pub fn reset(&mut self, cycles: u64) {
+        self.set_direction(0, cycles);
+        self.data = 0;
+        self.notify(cycles);
+    }
+
+    fn notify(&mut self, cycles: u64) {
+        let lines = self.lines(cycles);
+        if let Some(callback) = self.on_write.as_mut() {
+            callback(lines);
+        }
+    }
+
+    // Lines that stop being outputs keep the level they were driven at
+    fn set_direction(&mut self, direction: u8, cycles: u64) {
+        let released = self.direction & !direction;
+        for bit in (0..8).filter(|bit| released & (1 << bit) != 0) {
+            self.released[bit] = cycles;
+        }
+        self.charge = (self.charge & !released) | (self.data & released);
+        self.direction = direction;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    pub fn pull_ups() {
+        let mut port = IoPort::new();
+        assert_eq!(port.read(1, 0), 0x17);
+
+        port.write(0, 0x07, 0);
+        port.write(1, 0x05, 0);
+        assert_eq!(port.read(0, 0), 0x07);
+        assert_eq!(port.read(1, 0), 0x15);
+    }
+
+    #[test]
+    pub fn inputs() {
+        let mut port = IoPort::new();
+        // Pressing play pulls the cassette sense line low
+        port.set_input(0x00, 0x10);
+        assert_eq!(port.read(1, 0), 0x07);
+
+        // Outputs win over external inputs
+        port.write(1, 0x10, 0);
+        port.write(0, 0x10, 0);
+        assert_eq!(port.read(1, 0), 0x17);
+    }
+
+    #[test]
+    pub fn floating_lines() {
+        let mut port = IoPort::new();
+        port.write(0, 0xC0, 0);
+        port.write(1, 0xC0, 0);
+        port.write(0, 0x00, 100);
+
+        assert_eq!(port.read(1, 100 + DEFAULT_FALLOFF_CYCLES - 1), 0xD7);
+        assert_eq!(port.read(1, 100 + DEFAULT_FALLOFF_CYCLES), 0x17);
+    }
+
+    #[test]
+    pub fn write_callback() {
+        let mut port = IoPort::new();
+        let lines = Rc::new(Cell::new(0));
+        let seen = lines.clone();
+        port.on_write(move |value| seen.set(value));
+
+        port.write(0, 0x2F, 0);
+        assert_eq!(lines.get(), 0x10);
+        port.write(1, 0x37, 0);
+        assert_eq!(lines.get(), 0x37);
+    }
+}
@@ -1,10 +1,13 @@
-use crate::memory::Memory;
+use crate::bus::Bus;
 
+const UNUSED: u8 = 0x20;
+const BREAK: u8 = 0x10;
+
+// The B and unused bits are not part of the register, they only exist in the byte pushed on the
+// stack. They are set for BRK and PHP and B is clear for IRQ and NMI, pulling ignores both.
 pub struct Status {
     pub negative: bool,
     pub overflow: bool,
-    pub unused: bool,
-    pub brk: bool,
     pub decimal: bool,
     pub interrupt: bool,
     pub zero: bool,
@@ -22,8 +25,6 @@ impl Status {
         Status {
             negative: false,
             overflow: false,
-            unused: true,
-            brk: false,
             decimal: false,
             interrupt: false,
             zero: false,
@@ -31,23 +32,30 @@ impl Status {
         }
     }
 
+    // The unused bit always reads as set, B as clear
     pub fn to_binary(&self) -> u8 {
         (self.negative as u8) << 7
             | (self.overflow as u8) << 6
-            | (self.unused as u8) << 5
-            | (self.brk as u8) << 4
+            | UNUSED
             | (self.decimal as u8) << 3
             | (self.interrupt as u8) << 2
             | (self.zero as u8) << 1
             | self.carry as u8
     }
 
+    // The byte pushed on the stack, with B set for BRK and PHP
+    pub fn to_stack(&self, brk: bool) -> u8 {
+        if brk {
+            self.to_binary() | BREAK
+        } else {
+            self.to_binary()
+        }
+    }
+
     pub fn from_binary(status_binary: u8) -> Status {
         Status {
             negative: (status_binary & 0x80) != 0,
             overflow: (status_binary & 0x40) != 0,
-            unused: (status_binary & 0x20) != 0,
-            brk: (status_binary & 0x10) != 0,
             decimal: (status_binary & 0x8) != 0,
             interrupt: (status_binary & 0x4) != 0,
             zero: (status_binary & 0x2) != 0,
@@ -83,14 +91,14 @@ impl Registers {
         }
     }
 
-    pub fn push(&mut self, value: u8, memory: &mut Memory) {
-        memory.write_byte(0x100 + self.sp as u16, value);
+    pub fn push(&mut self, value: u8, memory: &mut impl Bus) {
+        memory.write(0x100 + self.sp as u16, value);
         self.sp = self.sp.wrapping_sub(1);
     }
 
-    pub fn pop(&mut self, memory: &Memory) -> u8 {
+    pub fn pop(&mut self, memory: &mut impl Bus) -> u8 {
         self.sp = self.sp.wrapping_add(1);
-        memory.get_byte(0x100 + self.sp as u16)
+        memory.read(0x100 + self.sp as u16)
     }
 }
 
@@ -108,6 +116,21 @@ mod tests {
     #[test]
     pub fn from_binary() {
         let status = Status::from_binary(0x80);
-        assert_eq!(status.negative, true);
+        assert!(status.negative);
+    }
+
+    #[test]
+    pub fn to_stack() {
+        let status = Status::from_binary(0x01);
+
+        assert_eq!(status.to_stack(true), 0x31);
+        assert_eq!(status.to_stack(false), 0x21);
+    }
+
+    #[test]
+    pub fn from_binary_ignores_stack_bits() {
+        let status = Status::from_binary(0xFF);
+
+        assert_eq!(status.to_binary(), 0xEF);
     }
 }
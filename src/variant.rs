@@ -0,0 +1,163 @@
+use crate::instructions::{AddressingMode, Instruction};
+use crate::registers::Status;
+
+// The differences between chips of the 6502 family, as seen by both cores. Only `decode` has to
+// be implemented, the defaults describe an NMOS 6502. Downstream crates can implement it for
+// other derivatives and pass them to `CPU::with_variant`.
+pub trait Variant {
+    // Opcode table, None for opcodes the chip can not execute
+    fn decode(&self, opcode: u8) -> Option<Instruction>;
+
+    // CMOS timing and fixes: decimal ADC and SBC take an extra cycle, indexed shifts only take
+    // their last cycle when crossing a page, read-modify-write instructions read twice instead
+    // of writing twice, page crossings reread the last operand byte and interrupts clear D
+    fn cmos(&self) -> bool {
+        false
+    }
+
+    // Whether ADC and SBC honour the decimal flag, which can always be set and cleared
+    fn decimal_mode(&self) -> bool {
+        true
+    }
+
+    // Address lines present on the chip, the rest of the address space mirrors them
+    fn address_mask(&self) -> u16 {
+        0xFFFF
+    }
+
+    fn interrupt_inputs(&self) -> bool {
+        true
+    }
+
+    // Whether the chip has the I/O port of the 6510 at $0000 and $0001
+    fn io_port(&self) -> bool {
+        false
+    }
+
+    fn adc(&self, accumulator: &mut u8, status: &mut Status, value: u8) {
+        if self.cmos() {
+            Instruction::adc_65c02(accumulator, status, value);
+        } else if !self.decimal_mode() {
+            Instruction::adc_binary(accumulator, status, value);
+        } else {
+            Instruction::adc(accumulator, status, value);
+        }
+    }
+
+    fn sbc(&self, accumulator: &mut u8, status: &mut Status, value: u8) {
+        if self.cmos() {
+            Instruction::sbc_65c02(accumulator, status, value);
+        } else if !self.decimal_mode() {
+            Instruction::adc_binary(accumulator, status, !value);
+        } else {
+            Instruction::sbc(accumulator, status, value);
+        }
+    }
+
+    fn ror(&self, status: &mut Status, value: &mut u8) {
+        Instruction::ror(status, value);
+    }
+}
+
+// NMOS 6502, with its undocumented instructions and bugs
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Mos6502;
+
+impl Variant for Mos6502 {
+    fn decode(&self, opcode: u8) -> Option<Instruction> {
+        Instruction::decode(opcode)
+    }
+}
+
+// The first 6502s, sold before June 1976, where ROR shifts left like ASL without touching carry
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Mos6502RevA;
+
+impl Variant for Mos6502RevA {
+    fn decode(&self, opcode: u8) -> Option<Instruction> {
+        Instruction::decode(opcode)
+    }
+
+    fn ror(&self, status: &mut Status, value: &mut u8) {
+        *value <<= 1;
+        status.zero = *value == 0;
+        status.negative = *value & 0x80 != 0;
+    }
+}
+
+// WDC 65C02, where the undefined opcodes are NOPs
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Wdc65C02;
+
+impl Variant for Wdc65C02 {
+    fn decode(&self, opcode: u8) -> Option<Instruction> {
+        Instruction::decode_65c02(opcode)
+    }
+
+    fn cmos(&self) -> bool {
+        true
+    }
+}
+
+// Rockwell R65C02, a 65C02 with the bit instructions but without WAI and STP
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rockwell65C02;
+
+impl Variant for Rockwell65C02 {
+    fn decode(&self, opcode: u8) -> Option<Instruction> {
+        match opcode {
+            0xCB | 0xDB => Some(Instruction::NOP(AddressingMode::Implied, 1)),
+            _ => Instruction::decode_65c02(opcode),
+        }
+    }
+
+    fn cmos(&self) -> bool {
+        true
+    }
+}
+
+// Ricoh 2A03 of the NES, an NMOS 6502 without decimal mode
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Ricoh2A03;
+
+impl Variant for Ricoh2A03 {
+    fn decode(&self, opcode: u8) -> Option<Instruction> {
+        Instruction::decode(opcode)
+    }
+
+    fn decimal_mode(&self) -> bool {
+        false
+    }
+}
+
+// MOS 6510 of the C64, an NMOS 6502 with an I/O port at $0000 and $0001
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Mos6510;
+
+impl Variant for Mos6510 {
+    fn decode(&self, opcode: u8) -> Option<Instruction> {
+        Instruction::decode(opcode)
+    }
+
+    fn io_port(&self) -> bool {
+        true
+    }
+}
+
+// MOS 6507 of the Atari 2600, an NMOS 6502 with 13 address lines and no IRQ or NMI pins
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Mos6507;
+
+impl Variant for Mos6507 {
+    fn decode(&self, opcode: u8) -> Option<Instruction> {
+        Instruction::decode(opcode)
+    }
+
+    fn address_mask(&self) -> u16 {
+        0x1FFF
+    }
+
+    fn interrupt_inputs(&self) -> bool {
+        false
+    }
+}
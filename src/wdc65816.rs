@@ -0,0 +1,1330 @@
+use std::cell::Cell;
+use std::collections::HashSet;
+
+use crate::cpu::{CpuError, Interrupt, State, StepResult, StopHandle, StopReason};
+use crate::instructions::{AddressingMode, Instruction, Operation};
+use crate::memory::{Memory, MemoryError};
+use crate::registers::Status;
+
+// Size of the 24-bit address space
+pub const ADDRESS_SPACE: usize = 0x100_0000;
+
+const MEMORY_SELECT: u8 = 0x20;
+const INDEX_SELECT: u8 = 0x10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Vector {
+    Cop,
+    Brk,
+    Nmi,
+    Irq,
+}
+
+impl Vector {
+    // Native mode has its own vectors, BRK shares the IRQ vector in emulation mode
+    fn address(self, emulation: bool) -> u16 {
+        match (self, emulation) {
+            (Self::Cop, false) => 0xFFE4,
+            (Self::Brk, false) => 0xFFE6,
+            (Self::Nmi, false) => 0xFFEA,
+            (Self::Irq, false) => 0xFFEE,
+            (Self::Cop, true) => 0xFFF4,
+            (Self::Nmi, true) => 0xFFFA,
+            (Self::Brk | Self::Irq, true) => 0xFFFE,
+        }
+    }
+}
+
+// In native mode the M and X bits of the status register take the place of the unused and B bits,
+// they are kept here as `Status` only holds the flags shared with the 6502
+pub struct Registers65816 {
+    pub accumulator: u16, // C, A in the low byte and B in the high byte
+    pub x: u16,
+    pub y: u16,
+    pub sp: u16,
+    pub pc: u16,
+    pub direct_page: u16,
+    pub data_bank: u8,
+    pub program_bank: u8,
+    pub status: Status,
+    pub memory_select: bool, // M, set for an 8-bit accumulator and memory
+    pub index_select: bool,  // X, set for 8-bit index registers
+    pub emulation: bool,
+}
+
+impl Default for Registers65816 {
+    fn default() -> Self {
+        Registers65816::new()
+    }
+}
+
+impl Registers65816 {
+    pub fn new() -> Self {
+        Registers65816 {
+            accumulator: 0,
+            x: 0,
+            y: 0,
+            sp: 0x0100,
+            pc: 0,
+            direct_page: 0,
+            data_bank: 0,
+            program_bank: 0,
+            status: Status::default(),
+            memory_select: true,
+            index_select: true,
+            emulation: true,
+        }
+    }
+
+    pub fn wide_accumulator(&self) -> bool {
+        !self.memory_select
+    }
+
+    pub fn wide_index(&self) -> bool {
+        !self.index_select
+    }
+
+    // The byte pushed by PHP and interrupts, with B set for BRK and PHP in emulation mode
+    pub fn status_to_stack(&self, brk: bool) -> u8 {
+        if self.emulation {
+            self.status.to_stack(brk)
+        } else {
+            let widths = (self.memory_select as u8) << 5 | (self.index_select as u8) << 4;
+            self.status.to_binary() & !(MEMORY_SELECT | INDEX_SELECT) | widths
+        }
+    }
+
+    pub fn set_status(&mut self, value: u8) {
+        self.status = Status::from_binary(value);
+        self.memory_select = value & MEMORY_SELECT != 0;
+        self.index_select = value & INDEX_SELECT != 0;
+        self.apply_widths();
+    }
+
+    // Swaps the carry and emulation bits, as XCE does
+    pub fn exchange_emulation(&mut self) {
+        std::mem::swap(&mut self.status.carry, &mut self.emulation);
+        self.apply_widths();
+    }
+
+    // Emulation mode forces 8-bit registers and a stack in page one, 8-bit index registers lose
+    // their high byte while B is kept
+    fn apply_widths(&mut self) {
+        if self.emulation {
+            self.memory_select = true;
+            self.index_select = true;
+            self.sp = 0x0100 | (self.sp & 0xFF);
+        }
+        if self.index_select {
+            self.x &= 0xFF;
+            self.y &= 0xFF;
+        }
+    }
+
+    pub fn get_accumulator(&self) -> u16 {
+        if self.wide_accumulator() {
+            self.accumulator
+        } else {
+            self.accumulator & 0xFF
+        }
+    }
+
+    pub fn set_accumulator(&mut self, value: u16) {
+        if self.wide_accumulator() {
+            self.accumulator = value;
+        } else {
+            self.accumulator = (self.accumulator & 0xFF00) | (value & 0xFF);
+        }
+    }
+
+    fn index_value(&self, value: u16) -> u16 {
+        if self.wide_index() {
+            value
+        } else {
+            value & 0xFF
+        }
+    }
+
+    pub fn set_nz(&mut self, value: u16, wide: bool) {
+        let (value, sign) = if wide {
+            (value, 0x8000)
+        } else {
+            (value & 0xFF, 0x80)
+        };
+        self.status.zero = value == 0;
+        self.status.negative = value & sign != 0;
+    }
+}
+
+pub struct CPU65816 {
+    pub memory: Memory,
+    pub registers: Registers65816,
+    pub cycles: u64,
+    pub state: State,
+    irq: bool,
+    nmi: bool,
+    nmi_pending: bool,
+    reset_pending: bool,
+    error: Cell<Option<MemoryError>>, // first failed access of the current step
+    // 24-bit addresses `run` stops at before executing the instruction there
+    pub breakpoints: HashSet<u32>,
+    stop_handle: StopHandle,
+}
+
+impl Default for CPU65816 {
+    fn default() -> Self {
+        CPU65816::new(Memory::new(ADDRESS_SPACE))
+    }
+}
+
+impl CPU65816 {
+    pub fn new(memory: Memory) -> Self {
+        CPU65816 {
+            memory,
+            registers: Registers65816::default(),
+            cycles: 0,
+            state: State::Running,
+            irq: false,
+            nmi: false,
+            nmi_pending: false,
+            reset_pending: false,
+            error: Cell::new(None),
+            breakpoints: HashSet::new(),
+            stop_handle: StopHandle::default(),
+        }
+    }
+
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq = asserted;
+    }
+
+    pub fn set_nmi(&mut self, asserted: bool) {
+        if asserted && !self.nmi {
+            self.nmi_pending = true;
+        }
+        self.nmi = asserted;
+    }
+
+    // The reset sequence runs at the next step, it returns to emulation mode
+    pub fn reset(&mut self) {
+        self.reset_pending = true;
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop_handle.clone()
+    }
+
+    // Runs the next instruction or interrupt sequence, see `CPU::step`. The address in the result
+    // is the PC within the program bank. MVN and MVP move a single byte per step. Accesses past
+    // the end of memory read 0 and are reported as an error once the instruction has completed.
+    pub fn step(&mut self) -> Result<StepResult, CpuError> {
+        let pc = self.registers.pc;
+        let result = self.step_instruction()?;
+        match self.error.take() {
+            Some(error) => Err(CpuError::Memory { pc, error }),
+            None => Ok(result),
+        }
+    }
+
+    fn step_instruction(&mut self) -> Result<StepResult, CpuError> {
+        let pc = self.registers.pc;
+        let serviced = |cycles, interrupt| StepResult {
+            instruction: None,
+            address: pc,
+            cycles,
+            interrupt: Some(interrupt),
+        };
+
+        if self.reset_pending {
+            return Ok(serviced(self.reset_sequence(), Interrupt::Reset));
+        }
+        if self.nmi_pending && self.state != State::Stopped {
+            self.nmi_pending = false;
+            return Ok(serviced(self.interrupt(Vector::Nmi), Interrupt::Nmi));
+        }
+        // A masked IRQ ends WAI without being serviced
+        if self.state == State::Waiting && self.irq {
+            self.state = State::Running;
+        }
+        if self.state != State::Running {
+            return Ok(StepResult {
+                instruction: None,
+                address: pc,
+                cycles: 0,
+                interrupt: None,
+            });
+        }
+        if self.irq && !self.registers.status.interrupt {
+            return Ok(serviced(self.interrupt(Vector::Irq), Interrupt::Irq));
+        }
+
+        let opcode = self.fetch_byte();
+        match Instruction::decode_65816(opcode) {
+            Some(instruction) => {
+                let cycles = self.execute(instruction);
+                self.cycles += cycles as u64;
+                Ok(StepResult {
+                    instruction: Some(instruction),
+                    address: pc,
+                    cycles,
+                    interrupt: None,
+                })
+            }
+            None => {
+                self.registers.pc = pc;
+                Err(CpuError::IllegalOpcode { pc, opcode })
+            }
+        }
+    }
+
+    // Steps until the CPU stops running, hits a breakpoint, fails or is asked to stop, like
+    // `CPU::run`. Breakpoints are reported with the PC within the program bank.
+    pub fn run(&mut self) -> StopReason {
+        let mut first = true;
+        loop {
+            if self.stop_handle.take() {
+                return StopReason::HostRequest;
+            }
+            let address = CPU65816::long(self.registers.program_bank, self.registers.pc);
+            if !first && self.breakpoints.contains(&address) {
+                return StopReason::Breakpoint(self.registers.pc);
+            }
+            first = false;
+
+            if let Err(error) = self.step() {
+                return error.into();
+            }
+            match self.state {
+                State::Running => {}
+                State::Jammed => return StopReason::Jammed,
+                State::Waiting => return StopReason::Waiting,
+                State::Stopped => return StopReason::Stopped,
+            }
+        }
+    }
+
+    fn reset_sequence(&mut self) -> u8 {
+        self.reset_pending = false;
+        self.nmi_pending = false;
+        self.state = State::Running;
+
+        let registers = &mut self.registers;
+        registers.emulation = true;
+        registers.direct_page = 0;
+        registers.data_bank = 0;
+        registers.program_bank = 0;
+        registers.sp = registers.sp.wrapping_sub(3);
+        registers.status.interrupt = true;
+        registers.status.decimal = false;
+        registers.apply_widths();
+        self.registers.pc = self.read_word_bank0(0xFFFC);
+
+        self.cycles += 7;
+        7
+    }
+
+    // Native mode also pushes the program bank, which takes an extra cycle
+    fn interrupt(&mut self, vector: Vector) -> u8 {
+        self.state = State::Running;
+        let emulation = self.registers.emulation;
+        if !emulation {
+            self.push(self.registers.program_bank);
+        }
+        self.push_word(self.registers.pc);
+        let brk = matches!(vector, Vector::Brk | Vector::Cop);
+        self.push(self.registers.status_to_stack(brk));
+
+        self.registers.status.interrupt = true;
+        self.registers.status.decimal = false;
+        self.registers.program_bank = 0;
+        self.registers.pc = self.read_word_bank0(vector.address(emulation));
+
+        let cycles = if emulation { 7 } else { 8 };
+        if !brk {
+            self.cycles += cycles as u64;
+        }
+        cycles
+    }
+
+    fn long(bank: u8, address: u16) -> u32 {
+        (bank as u32) << 16 | address as u32
+    }
+
+    // Failed accesses are kept for `step` to report, reads then see 0 and writes are dropped
+    pub fn read_byte(&self, address: u32) -> u8 {
+        self.memory
+            .get_byte_long(address & 0xFF_FFFF)
+            .unwrap_or_else(|error| {
+                self.fail(error);
+                0
+            })
+    }
+
+    pub fn write_byte(&mut self, address: u32, value: u8) {
+        if let Err(error) = self.memory.write_byte_long(address & 0xFF_FFFF, value) {
+            self.fail(error);
+        }
+    }
+
+    fn fail(&self, error: MemoryError) {
+        if self.error.get().is_none() {
+            self.error.set(Some(error));
+        }
+    }
+
+    // Data words may cross into the next bank
+    fn read_data(&self, address: u32, wide: bool) -> u16 {
+        let low = self.read_byte(address) as u16;
+        if wide {
+            low | (self.read_byte(address.wrapping_add(1)) as u16) << 8
+        } else {
+            low
+        }
+    }
+
+    fn write_data(&mut self, address: u32, value: u16, wide: bool) {
+        self.write_byte(address, value as u8);
+        if wide {
+            self.write_byte(address.wrapping_add(1), (value >> 8) as u8);
+        }
+    }
+
+    // Pointers in the direct page and stack never leave bank 0
+    fn read_word_bank0(&self, address: u16) -> u16 {
+        let low = self.read_byte(address as u32) as u16;
+        low | (self.read_byte(address.wrapping_add(1) as u32) as u16) << 8
+    }
+
+    fn read_long_bank0(&self, address: u16) -> u32 {
+        let bank = self.read_byte(address.wrapping_add(2) as u32) as u32;
+        bank << 16 | self.read_word_bank0(address) as u32
+    }
+
+    // The program counter wraps within its bank
+    fn fetch_byte(&mut self) -> u8 {
+        let registers = &self.registers;
+        let value = self.read_byte(CPU65816::long(registers.program_bank, registers.pc));
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch_word(&mut self) -> u16 {
+        let low = self.fetch_byte() as u16;
+        low | (self.fetch_byte() as u16) << 8
+    }
+
+    fn fetch_long(&mut self) -> u32 {
+        let word = self.fetch_word() as u32;
+        word | (self.fetch_byte() as u32) << 16
+    }
+
+    // The stack stays in page one in emulation mode
+    fn push(&mut self, value: u8) {
+        self.write_byte(self.registers.sp as u32, value);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.registers.apply_widths();
+    }
+
+    fn push_word(&mut self, value: u16) {
+        self.push((value >> 8) as u8);
+        self.push(value as u8);
+    }
+
+    fn pop(&mut self) -> u8 {
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        self.registers.apply_widths();
+        self.read_byte(self.registers.sp as u32)
+    }
+
+    fn pop_word(&mut self) -> u16 {
+        let low = self.pop() as u16;
+        low | (self.pop() as u16) << 8
+    }
+
+    fn push_data(&mut self, value: u16, wide: bool) {
+        if wide {
+            self.push_word(value);
+        } else {
+            self.push(value as u8);
+        }
+    }
+
+    fn pop_data(&mut self, wide: bool) -> u16 {
+        if wide {
+            self.pop_word()
+        } else {
+            self.pop() as u16
+        }
+    }
+
+    // The direct page wraps within page zero in emulation mode, as long as it is page aligned
+    fn direct_address(&self, offset: u8, index: u16) -> u16 {
+        let direct_page = self.registers.direct_page;
+        if self.registers.emulation && direct_page & 0xFF == 0 {
+            direct_page | offset.wrapping_add(index as u8) as u16
+        } else {
+            direct_page.wrapping_add(offset as u16).wrapping_add(index)
+        }
+    }
+
+    fn data_address(&self, address: u16) -> u32 {
+        CPU65816::long(self.registers.data_bank, address)
+    }
+
+    // Width of the value read or written, index instructions follow X and the others M
+    fn wide_data(&self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::LDX(..)
+            | Instruction::LDY(..)
+            | Instruction::STX(..)
+            | Instruction::STY(..)
+            | Instruction::CPX(..)
+            | Instruction::CPY(..) => self.registers.wide_index(),
+            Instruction::REP(..)
+            | Instruction::SEP(..)
+            | Instruction::WDM(..)
+            | Instruction::BRK(..)
+            | Instruction::COP(..) => false,
+            _ => self.registers.wide_accumulator(),
+        }
+    }
+
+    // Returns the effective address and the extra cycles of a relocated direct page, and of
+    // indexing across a page or with 16-bit index registers for reads
+    fn operand_address(&mut self, instruction: &Instruction) -> (u32, u8) {
+        let direct_penalty = (self.registers.direct_page & 0xFF != 0) as u8;
+        let (x, y) = (self.registers.x, self.registers.y);
+        let indexed = |base: u32, index: u16, wide_index: bool| {
+            let address = base.wrapping_add(index as u32) & 0xFF_FFFF;
+            let penalty = wide_index || (base & 0xFFFF00) != (address & 0xFFFF00);
+            let read = instruction.operation() == Operation::Read;
+            (address, (read && penalty) as u8)
+        };
+        let wide_index = self.registers.wide_index();
+
+        match instruction.addressing_mode() {
+            AddressingMode::Accumulator | AddressingMode::Implied => (0, 0),
+            AddressingMode::Immediate => {
+                let address = CPU65816::long(self.registers.program_bank, self.registers.pc);
+                let bytes = 1 + self.wide_data(instruction) as u16;
+                self.registers.pc = self.registers.pc.wrapping_add(bytes);
+                (address, 0)
+            }
+            AddressingMode::Absolute => {
+                let address = self.fetch_word();
+                (self.data_address(address), 0)
+            }
+            AddressingMode::AbsoluteX => {
+                let address = self.fetch_word();
+                indexed(self.data_address(address), x, wide_index)
+            }
+            AddressingMode::AbsoluteY => {
+                let address = self.fetch_word();
+                indexed(self.data_address(address), y, wide_index)
+            }
+            AddressingMode::AbsoluteLong => (self.fetch_long(), 0),
+            AddressingMode::AbsoluteLongX => {
+                let address = self.fetch_long();
+                (address.wrapping_add(x as u32) & 0xFF_FFFF, 0)
+            }
+            AddressingMode::Indirect | AddressingMode::AbsoluteIndirect => {
+                let pointer = self.fetch_word();
+                (self.read_word_bank0(pointer) as u32, 0)
+            }
+            AddressingMode::AbsoluteIndirectX => {
+                let pointer = self.fetch_word().wrapping_add(x);
+                let bank = self.registers.program_bank;
+                let low = self.read_byte(CPU65816::long(bank, pointer)) as u16;
+                let high = self.read_byte(CPU65816::long(bank, pointer.wrapping_add(1))) as u16;
+                ((high << 8 | low) as u32, 0)
+            }
+            AddressingMode::AbsoluteIndirectLong => {
+                let pointer = self.fetch_word();
+                (self.read_long_bank0(pointer), 0)
+            }
+            AddressingMode::ZeroPage => {
+                let offset = self.fetch_byte();
+                (self.direct_address(offset, 0) as u32, direct_penalty)
+            }
+            AddressingMode::ZeroPageX => {
+                let offset = self.fetch_byte();
+                (self.direct_address(offset, x) as u32, direct_penalty)
+            }
+            AddressingMode::ZeroPageY => {
+                let offset = self.fetch_byte();
+                (self.direct_address(offset, y) as u32, direct_penalty)
+            }
+            AddressingMode::ZeroPageIndirect => {
+                let offset = self.fetch_byte();
+                let pointer = self.read_word_bank0(self.direct_address(offset, 0));
+                (self.data_address(pointer), direct_penalty)
+            }
+            AddressingMode::IndirectX => {
+                let offset = self.fetch_byte();
+                let pointer = self.read_word_bank0(self.direct_address(offset, x));
+                (self.data_address(pointer), direct_penalty)
+            }
+            AddressingMode::IndirectY => {
+                let offset = self.fetch_byte();
+                let pointer = self.read_word_bank0(self.direct_address(offset, 0));
+                let (address, penalty) = indexed(self.data_address(pointer), y, wide_index);
+                (address, penalty + direct_penalty)
+            }
+            AddressingMode::DirectIndirectLong => {
+                let offset = self.fetch_byte();
+                let address = self.read_long_bank0(self.direct_address(offset, 0));
+                (address, direct_penalty)
+            }
+            AddressingMode::DirectIndirectLongY => {
+                let offset = self.fetch_byte();
+                let address = self.read_long_bank0(self.direct_address(offset, 0));
+                (address.wrapping_add(y as u32) & 0xFF_FFFF, direct_penalty)
+            }
+            AddressingMode::StackRelative => {
+                let offset = self.fetch_byte() as u16;
+                (self.registers.sp.wrapping_add(offset) as u32, 0)
+            }
+            AddressingMode::StackRelativeIndirectY => {
+                let offset = self.fetch_byte() as u16;
+                let pointer = self.read_word_bank0(self.registers.sp.wrapping_add(offset));
+                let address = self.data_address(pointer).wrapping_add(y as u32);
+                (address & 0xFF_FFFF, 0)
+            }
+            AddressingMode::Relative => (self.fetch_byte() as i8 as u16 as u32, 0),
+            AddressingMode::RelativeLong | AddressingMode::BlockMove => {
+                (self.fetch_word() as u32, 0)
+            }
+            AddressingMode::ZeroPageRelative => {
+                unreachable!("{:?} is not a 65816 instruction", instruction)
+            }
+        }
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> u8 {
+        let (address, mut cycles) = self.operand_address(&instruction);
+        cycles += instruction.cycles();
+        let wide = self.wide_data(&instruction);
+
+        match instruction.operation() {
+            Operation::Read => {
+                let value = self.read_data(address, wide);
+                self.read_operation(&instruction, value);
+                cycles += wide as u8;
+            }
+            Operation::Write => {
+                let value = self.write_operation(&instruction);
+                self.write_data(address, value, wide);
+                cycles += wide as u8;
+            }
+            Operation::ReadModifyWrite => {
+                if *instruction.addressing_mode() == AddressingMode::Accumulator {
+                    let value = self.registers.get_accumulator();
+                    let result = self.modify_operation(&instruction, value);
+                    self.registers.set_accumulator(result);
+                } else {
+                    let value = self.read_data(address, wide);
+                    let result = self.modify_operation(&instruction, value);
+                    self.write_data(address, result, wide);
+                    cycles += 2 * wide as u8;
+                }
+            }
+            Operation::Implied => self.implied_operation(&instruction),
+            Operation::Control => cycles += self.control_operation(&instruction, address),
+        }
+        cycles
+    }
+
+    fn adc(&mut self, value: u16) {
+        let wide = self.registers.wide_accumulator();
+        let (mask, sign, digits) = if wide {
+            (0xFFFF, 0x8000, 4)
+        } else {
+            (0xFF, 0x80, 2)
+        };
+        let accumulator = self.registers.get_accumulator() as u32;
+        let value = value as u32;
+        let status = &mut self.registers.status;
+        let mut carry = status.carry as u32;
+
+        let result = if status.decimal {
+            let mut result = 0;
+            for shift in (0..digits).map(|digit| digit * 4) {
+                let mut digit = ((accumulator >> shift) & 0xF) + ((value >> shift) & 0xF) + carry;
+                carry = (digit > 9) as u32;
+                if carry == 1 {
+                    digit += 6;
+                }
+                result |= (digit & 0xF) << shift;
+            }
+            result
+        } else {
+            let sum = accumulator + value + carry;
+            carry = (sum > mask) as u32;
+            sum & mask
+        };
+
+        status.carry = carry == 1;
+        status.overflow = !(accumulator ^ value) & (accumulator ^ result) & sign != 0;
+        self.registers.set_accumulator(result as u16);
+        self.registers.set_nz(result as u16, wide);
+    }
+
+    fn sbc(&mut self, value: u16) {
+        let wide = self.registers.wide_accumulator();
+        if !self.registers.status.decimal {
+            let mask = if wide { 0xFFFF } else { 0xFF };
+            self.adc(!value & mask);
+            return;
+        }
+
+        let (sign, digits) = if wide { (0x8000, 4) } else { (0x80, 2) };
+        let accumulator = self.registers.get_accumulator() as i32;
+        let value = value as i32;
+        let status = &mut self.registers.status;
+        let mut borrow = !status.carry as i32;
+
+        let mut result = 0;
+        for shift in (0..digits).map(|digit| digit * 4) {
+            let mut digit = ((accumulator >> shift) & 0xF) - ((value >> shift) & 0xF) - borrow;
+            borrow = (digit < 0) as i32;
+            if borrow == 1 {
+                digit += 10;
+            }
+            result |= (digit & 0xF) << shift;
+        }
+
+        status.carry = borrow == 0;
+        status.overflow = (accumulator ^ value) & (accumulator ^ result) & sign != 0;
+        self.registers.set_accumulator(result as u16);
+        self.registers.set_nz(result as u16, wide);
+    }
+
+    fn compare(&mut self, register: u16, value: u16, wide: bool) {
+        let mask = if wide { 0xFFFF } else { 0xFF };
+        let result = register.wrapping_sub(value) & mask;
+        self.registers.status.carry = register >= value;
+        self.registers.set_nz(result, wide);
+    }
+
+    fn read_operation(&mut self, instruction: &Instruction, value: u16) {
+        let wide = self.registers.wide_accumulator();
+        let wide_index = self.registers.wide_index();
+        let accumulator = self.registers.get_accumulator();
+        let registers = &mut self.registers;
+        match instruction {
+            Instruction::ADC(..) => self.adc(value),
+            Instruction::SBC(..) => self.sbc(value),
+            Instruction::AND(..) => {
+                registers.set_accumulator(accumulator & value);
+                registers.set_nz(accumulator & value, wide);
+            }
+            Instruction::EOR(..) => {
+                registers.set_accumulator(accumulator ^ value);
+                registers.set_nz(accumulator ^ value, wide);
+            }
+            Instruction::ORA(..) => {
+                registers.set_accumulator(accumulator | value);
+                registers.set_nz(accumulator | value, wide);
+            }
+            Instruction::BIT(AddressingMode::Immediate, _) => {
+                registers.status.zero = accumulator & value == 0;
+            }
+            Instruction::BIT(..) => {
+                let sign = if wide { 0x8000 } else { 0x80 };
+                registers.status.zero = accumulator & value == 0;
+                registers.status.negative = value & sign != 0;
+                registers.status.overflow = value & (sign >> 1) != 0;
+            }
+            Instruction::CMP(..) => self.compare(accumulator, value, wide),
+            Instruction::CPX(..) => self.compare(self.registers.x, value, wide_index),
+            Instruction::CPY(..) => self.compare(self.registers.y, value, wide_index),
+            Instruction::LDA(..) => {
+                registers.set_accumulator(value);
+                registers.set_nz(value, wide);
+            }
+            Instruction::LDX(..) => {
+                registers.x = value;
+                registers.set_nz(value, wide_index);
+            }
+            Instruction::LDY(..) => {
+                registers.y = value;
+                registers.set_nz(value, wide_index);
+            }
+            Instruction::REP(..) => {
+                let status = registers.status_to_stack(false) & !(value as u8);
+                registers.set_status(status);
+            }
+            Instruction::SEP(..) => {
+                let status = registers.status_to_stack(false) | value as u8;
+                registers.set_status(status);
+            }
+            Instruction::NOP(..) | Instruction::WDM(..) => {}
+            _ => unreachable!("{:?} is not a read instruction", instruction),
+        }
+    }
+
+    fn write_operation(&mut self, instruction: &Instruction) -> u16 {
+        let registers = &self.registers;
+        match instruction {
+            Instruction::STA(..) => registers.get_accumulator(),
+            Instruction::STX(..) => registers.x,
+            Instruction::STY(..) => registers.y,
+            Instruction::STZ(..) => 0,
+            _ => unreachable!("{:?} is not a write instruction", instruction),
+        }
+    }
+
+    fn modify_operation(&mut self, instruction: &Instruction, value: u16) -> u16 {
+        let wide = self.registers.wide_accumulator();
+        let (mask, sign) = if wide { (0xFFFF, 0x8000) } else { (0xFF, 0x80) };
+        let accumulator = self.registers.get_accumulator();
+        let registers = &mut self.registers;
+        let carry = registers.status.carry as u16;
+
+        let result = match instruction {
+            Instruction::ASL(..) => {
+                registers.status.carry = value & sign != 0;
+                (value << 1) & mask
+            }
+            Instruction::LSR(..) => {
+                registers.status.carry = value & 0x01 != 0;
+                value >> 1
+            }
+            Instruction::ROL(..) => {
+                registers.status.carry = value & sign != 0;
+                ((value << 1) | carry) & mask
+            }
+            Instruction::ROR(..) => {
+                registers.status.carry = value & 0x01 != 0;
+                (value >> 1) | if carry == 1 { sign } else { 0 }
+            }
+            Instruction::INC(..) => value.wrapping_add(1) & mask,
+            Instruction::DEC(..) => value.wrapping_sub(1) & mask,
+            // TRB and TSB only set Z, from the bits the accumulator and memory have in common
+            Instruction::TRB(..) => {
+                registers.status.zero = accumulator & value == 0;
+                return value & !accumulator;
+            }
+            Instruction::TSB(..) => {
+                registers.status.zero = accumulator & value == 0;
+                return value | accumulator;
+            }
+            _ => unreachable!("{:?} is not a read-modify-write instruction", instruction),
+        };
+        registers.set_nz(result, wide);
+        result
+    }
+
+    fn implied_operation(&mut self, instruction: &Instruction) {
+        let wide = self.registers.wide_accumulator();
+        let wide_index = self.registers.wide_index();
+        let registers = &mut self.registers;
+        match instruction {
+            Instruction::CLC(..) => registers.status.carry = false,
+            Instruction::CLD(..) => registers.status.decimal = false,
+            Instruction::CLI(..) => registers.status.interrupt = false,
+            Instruction::CLV(..) => registers.status.overflow = false,
+            Instruction::SEC(..) => registers.status.carry = true,
+            Instruction::SED(..) => registers.status.decimal = true,
+            Instruction::SEI(..) => registers.status.interrupt = true,
+            Instruction::DEX(..) => {
+                registers.x = registers.index_value(registers.x.wrapping_sub(1));
+                registers.set_nz(registers.x, wide_index);
+            }
+            Instruction::DEY(..) => {
+                registers.y = registers.index_value(registers.y.wrapping_sub(1));
+                registers.set_nz(registers.y, wide_index);
+            }
+            Instruction::INX(..) => {
+                registers.x = registers.index_value(registers.x.wrapping_add(1));
+                registers.set_nz(registers.x, wide_index);
+            }
+            Instruction::INY(..) => {
+                registers.y = registers.index_value(registers.y.wrapping_add(1));
+                registers.set_nz(registers.y, wide_index);
+            }
+            // Transfers to the index registers take their width, so TAX copies B into X as well
+            // when only the accumulator is 8-bit
+            Instruction::TAX(..) => {
+                registers.x = registers.index_value(registers.accumulator);
+                registers.set_nz(registers.x, wide_index);
+            }
+            Instruction::TAY(..) => {
+                registers.y = registers.index_value(registers.accumulator);
+                registers.set_nz(registers.y, wide_index);
+            }
+            Instruction::TSX(..) => {
+                registers.x = registers.index_value(registers.sp);
+                registers.set_nz(registers.x, wide_index);
+            }
+            Instruction::TXY(..) => {
+                registers.y = registers.x;
+                registers.set_nz(registers.y, wide_index);
+            }
+            Instruction::TYX(..) => {
+                registers.x = registers.y;
+                registers.set_nz(registers.x, wide_index);
+            }
+            Instruction::TXA(..) => {
+                registers.set_accumulator(registers.x);
+                registers.set_nz(registers.x, wide);
+            }
+            Instruction::TYA(..) => {
+                registers.set_accumulator(registers.y);
+                registers.set_nz(registers.y, wide);
+            }
+            Instruction::TXS(..) => {
+                registers.sp = registers.x;
+                registers.apply_widths();
+            }
+            Instruction::TCS(..) => {
+                registers.sp = registers.accumulator;
+                registers.apply_widths();
+            }
+            Instruction::TSC(..) => {
+                registers.accumulator = registers.sp;
+                registers.set_nz(registers.sp, true);
+            }
+            Instruction::TCD(..) => {
+                registers.direct_page = registers.accumulator;
+                registers.set_nz(registers.direct_page, true);
+            }
+            Instruction::TDC(..) => {
+                registers.accumulator = registers.direct_page;
+                registers.set_nz(registers.direct_page, true);
+            }
+            Instruction::XBA(..) => {
+                registers.accumulator = registers.accumulator.swap_bytes();
+                registers.set_nz(registers.accumulator, false);
+            }
+            Instruction::XCE(..) => registers.exchange_emulation(),
+            Instruction::NOP(..) => {}
+            _ => unreachable!("{:?} is not an implied instruction", instruction),
+        }
+    }
+
+    fn branch_condition(&self, instruction: &Instruction) -> bool {
+        let status = &self.registers.status;
+        match instruction {
+            Instruction::BCC(..) => !status.carry,
+            Instruction::BCS(..) => status.carry,
+            Instruction::BEQ(..) => status.zero,
+            Instruction::BMI(..) => status.negative,
+            Instruction::BNE(..) => !status.zero,
+            Instruction::BPL(..) => !status.negative,
+            Instruction::BVC(..) => !status.overflow,
+            Instruction::BVS(..) => status.overflow,
+            Instruction::BRA(..) => true,
+            _ => unreachable!("{:?} is not a branch instruction", instruction),
+        }
+    }
+
+    // Returns the extra cycles taken by branches, 16-bit pushes and pulls, and native mode
+    // interrupts
+    fn control_operation(&mut self, instruction: &Instruction, address: u32) -> u8 {
+        let wide = self.registers.wide_accumulator();
+        let wide_index = self.registers.wide_index();
+        match instruction {
+            _ if instruction.is_branch() => {
+                if !self.branch_condition(instruction) {
+                    return 0;
+                }
+                let pc = self.registers.pc;
+                self.registers.pc = pc.wrapping_add(address as u16);
+                let crossed = (pc & 0xFF00) != (self.registers.pc & 0xFF00);
+                return 1 + (crossed && self.registers.emulation) as u8;
+            }
+            Instruction::BRL(..) => {
+                self.registers.pc = self.registers.pc.wrapping_add(address as u16);
+            }
+            Instruction::BRK(..) => return self.interrupt(Vector::Brk) - 7,
+            Instruction::COP(..) => return self.interrupt(Vector::Cop) - 7,
+            Instruction::STP(..) => self.state = State::Stopped,
+            Instruction::WAI(..) => self.state = State::Waiting,
+            Instruction::JMP(..) => self.registers.pc = address as u16,
+            Instruction::JML(..) => {
+                self.registers.program_bank = (address >> 16) as u8;
+                self.registers.pc = address as u16;
+            }
+            Instruction::JSR(..) => {
+                self.push_word(self.registers.pc.wrapping_sub(1));
+                self.registers.pc = address as u16;
+            }
+            Instruction::JSL(..) => {
+                self.push(self.registers.program_bank);
+                self.push_word(self.registers.pc.wrapping_sub(1));
+                self.registers.program_bank = (address >> 16) as u8;
+                self.registers.pc = address as u16;
+            }
+            Instruction::RTS(..) => {
+                self.registers.pc = self.pop_word().wrapping_add(1);
+            }
+            Instruction::RTL(..) => {
+                self.registers.pc = self.pop_word().wrapping_add(1);
+                self.registers.program_bank = self.pop();
+            }
+            Instruction::RTI(..) => {
+                let status = self.pop();
+                self.registers.set_status(status);
+                self.registers.pc = self.pop_word();
+                if !self.registers.emulation {
+                    self.registers.program_bank = self.pop();
+                    return 1;
+                }
+            }
+            Instruction::PHA(..) => {
+                self.push_data(self.registers.get_accumulator(), wide);
+                return wide as u8;
+            }
+            Instruction::PHX(..) => {
+                self.push_data(self.registers.x, wide_index);
+                return wide_index as u8;
+            }
+            Instruction::PHY(..) => {
+                self.push_data(self.registers.y, wide_index);
+                return wide_index as u8;
+            }
+            Instruction::PHP(..) => self.push(self.registers.status_to_stack(true)),
+            Instruction::PHB(..) => self.push(self.registers.data_bank),
+            Instruction::PHK(..) => self.push(self.registers.program_bank),
+            Instruction::PHD(..) => self.push_word(self.registers.direct_page),
+            Instruction::PEA(..) | Instruction::PEI(..) => self.push_word(address as u16),
+            Instruction::PER(..) => {
+                self.push_word(self.registers.pc.wrapping_add(address as u16));
+            }
+            Instruction::PLA(..) => {
+                let value = self.pop_data(wide);
+                self.registers.set_accumulator(value);
+                self.registers.set_nz(value, wide);
+                return wide as u8;
+            }
+            Instruction::PLX(..) => {
+                self.registers.x = self.pop_data(wide_index);
+                self.registers.set_nz(self.registers.x, wide_index);
+                return wide_index as u8;
+            }
+            Instruction::PLY(..) => {
+                self.registers.y = self.pop_data(wide_index);
+                self.registers.set_nz(self.registers.y, wide_index);
+                return wide_index as u8;
+            }
+            Instruction::PLP(..) => {
+                let status = self.pop();
+                self.registers.set_status(status);
+            }
+            Instruction::PLB(..) => {
+                self.registers.data_bank = self.pop();
+                self.registers
+                    .set_nz(self.registers.data_bank as u16, false);
+            }
+            Instruction::PLD(..) => {
+                self.registers.direct_page = self.pop_word();
+                self.registers.set_nz(self.registers.direct_page, true);
+            }
+            Instruction::MVN(..) | Instruction::MVP(..) => self.block_move(instruction, address),
+            _ => unreachable!("{:?} is not a control instruction", instruction),
+        }
+        0
+    }
+
+    // Moves one byte from the source bank at X to the destination bank at Y, and runs again until
+    // C wraps to $FFFF. MVN moves up through memory, MVP down.
+    fn block_move(&mut self, instruction: &Instruction, banks: u32) {
+        let (destination, source) = (banks as u8, (banks >> 8) as u8);
+        let registers = &mut self.registers;
+        registers.data_bank = destination;
+
+        let value = self.read_byte(CPU65816::long(source, self.registers.x));
+        self.write_byte(CPU65816::long(destination, self.registers.y), value);
+
+        let registers = &mut self.registers;
+        let step = if matches!(instruction, Instruction::MVN(..)) {
+            1
+        } else {
+            0xFFFF
+        };
+        registers.x = registers.index_value(registers.x.wrapping_add(step));
+        registers.y = registers.index_value(registers.y.wrapping_add(step));
+        registers.accumulator = registers.accumulator.wrapping_sub(1);
+        if registers.accumulator != 0xFFFF {
+            registers.pc = registers.pc.wrapping_sub(3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(program: &[u8]) -> CPU65816 {
+        let mut cpu = CPU65816::default();
+        cpu.memory.poke_bytes_long(0x8000, program);
+        cpu.registers.pc = 0x8000;
+        cpu.registers.sp = 0x01FF;
+        cpu
+    }
+
+    fn steps(cpu: &mut CPU65816, count: usize) {
+        for _ in 0..count {
+            cpu.step().unwrap();
+        }
+    }
+
+    #[test]
+    pub fn register_widths() {
+        let mut cpu = load(&[
+            0x18, // CLC
+            0xFB, // XCE
+            0xC2, 0x30, // REP #$30
+            0xA9, 0x34, 0x12, // LDA #$1234
+            0x69, 0xCD, 0xAB, // ADC #$ABCD
+            0xAA, // TAX
+            0xE2, 0x20, // SEP #$20
+            0xA9, 0xFF, // LDA #$FF
+            0xEB, // XBA
+        ]);
+
+        steps(&mut cpu, 2);
+        assert!(!cpu.registers.emulation);
+        assert!(cpu.registers.status.carry);
+        steps(&mut cpu, 4);
+        assert_eq!(cpu.registers.accumulator, 0xBE02);
+        assert!(!cpu.registers.status.carry);
+        assert_eq!(cpu.registers.x, 0xBE02);
+        steps(&mut cpu, 2);
+        assert_eq!(cpu.registers.accumulator, 0xBEFF);
+        steps(&mut cpu, 1);
+        assert_eq!(cpu.registers.accumulator, 0xFFBE);
+        assert!(cpu.registers.status.negative);
+        assert_eq!(cpu.cycles, 23);
+
+        // Back in emulation mode the index registers lose their high byte
+        cpu.registers.status.carry = true;
+        cpu.registers.exchange_emulation();
+        assert_eq!(cpu.registers.x, 0x02);
+        assert!(cpu.registers.memory_select && cpu.registers.index_select);
+    }
+
+    #[test]
+    pub fn long_addressing() {
+        let mut cpu = load(&[
+            0x18, // CLC
+            0xFB, // XCE
+            0xA9, 0x42, // LDA #$42
+            0x8F, 0x00, 0x10, 0x7E, // STA $7E1000
+            0xA2, 0x05, // LDX #$05
+            0x9F, 0xFF, 0xFF, 0x01, // STA $01FFFF,X
+            0x22, 0x00, 0x90, 0x03, // JSL $039000
+            0xA9, 0x7E, // LDA #$7E
+            0x48, // PHA
+            0xAB, // PLB
+            0xAD, 0x00, 0x10, // LDA $1000
+        ]);
+        cpu.memory.poke_bytes_long(0x039000, &[0xA9, 0x99, 0x6B]); // LDA #$99, RTL
+
+        steps(&mut cpu, 6);
+        assert_eq!(cpu.memory.peek_long(0x7E1000), 0x42);
+        assert_eq!(cpu.memory.peek_long(0x020004), 0x42);
+        steps(&mut cpu, 1);
+        assert_eq!(cpu.registers.program_bank, 0x03);
+        assert_eq!(cpu.registers.pc, 0x9000);
+        assert_eq!(cpu.registers.sp, 0x01FC);
+        steps(&mut cpu, 2);
+        assert_eq!(cpu.registers.accumulator, 0x99);
+        assert_eq!(cpu.registers.program_bank, 0x00);
+        assert_eq!(cpu.registers.pc, 0x8012);
+        steps(&mut cpu, 4);
+        assert_eq!(cpu.registers.data_bank, 0x7E);
+        assert_eq!(cpu.registers.accumulator, 0x42);
+    }
+
+    #[test]
+    pub fn direct_page_and_stack() {
+        let mut cpu = load(&[
+            0x18, // CLC
+            0xFB, // XCE
+            0xC2, 0x20, // REP #$20
+            0xA9, 0x34, 0x12, // LDA #$1234
+            0x5B, // TCD
+            0xE2, 0x20, // SEP #$20
+            0xA5, 0x10, // LDA $10
+            0xF4, 0xEF, 0xBE, // PEA $BEEF
+            0xA3, 0x01, // LDA $01,S
+            0xA0, 0x02, // LDY #$02
+            0xB3, 0x01, // LDA ($01,S),Y
+            0xC2, 0x20, // REP #$20
+            0xA9, 0x00, 0x40, // LDA #$4000
+            0x1B, // TCS
+            0x48, // PHA
+            0x38, // SEC
+            0xFB, // XCE
+        ]);
+        cpu.memory.poke(0x1244, 0x77);
+        cpu.memory.poke(0xBEF1, 0x5A);
+
+        steps(&mut cpu, 6);
+        assert_eq!(cpu.registers.direct_page, 0x1234);
+        let cycles = cpu.cycles;
+        steps(&mut cpu, 1);
+        assert_eq!(cpu.registers.accumulator & 0xFF, 0x77);
+        // A direct page off a page boundary costs a cycle
+        assert_eq!(cpu.cycles - cycles, 4);
+
+        steps(&mut cpu, 2);
+        assert_eq!(cpu.registers.sp, 0x01FD);
+        assert_eq!(cpu.registers.accumulator & 0xFF, 0xEF);
+        steps(&mut cpu, 2);
+        assert_eq!(cpu.registers.accumulator & 0xFF, 0x5A);
+
+        steps(&mut cpu, 4);
+        assert_eq!(cpu.registers.sp, 0x3FFE);
+        assert_eq!(cpu.memory.peek(0x4000), 0x40);
+        assert_eq!(cpu.memory.peek(0x3FFF), 0x00);
+        steps(&mut cpu, 2);
+        assert!(cpu.registers.emulation);
+        assert_eq!(cpu.registers.sp, 0x01FE);
+    }
+
+    #[test]
+    pub fn block_move() {
+        let mut cpu = load(&[
+            0x18, // CLC
+            0xFB, // XCE
+            0xC2, 0x30, // REP #$30
+            0xA9, 0x03, 0x00, // LDA #$0003
+            0xA2, 0x00, 0x10, // LDX #$1000
+            0xA0, 0x00, 0x20, // LDY #$2000
+            0x54, 0x7F, 0x7E, // MVN $7E,$7F
+            0xEA, // NOP
+        ]);
+        cpu.memory.poke_bytes_long(0x7E1000, &[1, 2, 3, 4]);
+
+        steps(&mut cpu, 6);
+        let cycles = cpu.cycles;
+        while cpu.registers.pc == 0x800D {
+            steps(&mut cpu, 1);
+        }
+        assert_eq!(cpu.cycles - cycles, 4 * 7);
+        for (offset, value) in [1, 2, 3, 4].into_iter().enumerate() {
+            assert_eq!(cpu.memory.peek_long(0x7F2000 + offset as u32), value);
+        }
+        assert_eq!(cpu.registers.accumulator, 0xFFFF);
+        assert_eq!(cpu.registers.x, 0x1004);
+        assert_eq!(cpu.registers.y, 0x2004);
+        assert_eq!(cpu.registers.data_bank, 0x7F);
+        assert_eq!(cpu.registers.pc, 0x8010);
+    }
+
+    #[test]
+    pub fn decimal_mode() {
+        let mut cpu = load(&[
+            0x18, // CLC
+            0xFB, // XCE
+            0xF8, // SED
+            0xC2, 0x20, // REP #$20
+            0xA9, 0x99, 0x19, // LDA #$1999
+            0x18, // CLC
+            0x69, 0x01, 0x00, // ADC #$0001
+            0x38, // SEC
+            0xE9, 0x01, 0x00, // SBC #$0001
+        ]);
+
+        steps(&mut cpu, 7);
+        assert_eq!(cpu.registers.accumulator, 0x2000);
+        assert!(!cpu.registers.status.carry);
+        steps(&mut cpu, 2);
+        assert_eq!(cpu.registers.accumulator, 0x1999);
+        assert!(cpu.registers.status.carry);
+    }
+
+    #[test]
+    pub fn native_interrupts() {
+        let mut cpu = load(&[
+            0x18, // CLC
+            0xFB, // XCE
+            0x5C, 0x00, 0x80, 0x02, // JML $028000
+        ]);
+        cpu.memory
+            .poke_bytes_long(0x028000, &[0x58, 0xEA, 0x02, 0x00]); // CLI, NOP, COP #$00
+        cpu.memory.poke_bytes_long(0xFFE4, &[0x00, 0x91]);
+        cpu.memory.poke_bytes_long(0xFFEE, &[0x00, 0x90]);
+        cpu.memory.poke_bytes_long(0x9000, &[0x40]); // RTI
+        cpu.memory.poke_bytes_long(0x9100, &[0x40]); // RTI
+
+        steps(&mut cpu, 4);
+        cpu.set_irq(true);
+        let result = StepResult {
+            instruction: None,
+            address: 0x8001,
+            cycles: 8,
+            interrupt: Some(Interrupt::Irq),
+        };
+        assert_eq!(cpu.step(), Ok(result));
+        assert_eq!(cpu.registers.program_bank, 0x00);
+        assert_eq!(cpu.registers.pc, 0x9000);
+        assert_eq!(cpu.memory.peek(0x01FF), 0x02);
+        cpu.set_irq(false);
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert_eq!(cpu.registers.program_bank, 0x02);
+        assert_eq!(cpu.registers.pc, 0x8001);
+
+        steps(&mut cpu, 1);
+        assert_eq!(cpu.step().unwrap().cycles, 8);
+        assert_eq!(cpu.registers.pc, 0x9100);
+        assert!(cpu.registers.status.interrupt);
+        steps(&mut cpu, 1);
+        assert_eq!(cpu.registers.pc, 0x8004);
+        assert_eq!(cpu.registers.program_bank, 0x02);
+    }
+
+    #[test]
+    pub fn stop_reasons() {
+        let mut cpu = load(&[0x5C, 0x00, 0x80, 0x02]); // JML $028000
+        cpu.memory
+            .poke_bytes_long(0x028000, &[0xEA, 0x78, 0xCB, 0xDB]); // NOP, SEI, WAI, STP
+        cpu.breakpoints.insert(0x028001);
+        cpu.stop_handle().stop();
+
+        assert_eq!(cpu.run(), StopReason::HostRequest);
+        assert_eq!(cpu.run(), StopReason::Breakpoint(0x8001));
+        assert_eq!(cpu.registers.program_bank, 0x02);
+        assert_eq!(cpu.run(), StopReason::Waiting);
+        cpu.set_irq(true);
+        assert_eq!(cpu.run(), StopReason::Stopped);
+        assert_eq!(cpu.registers.pc, 0x8004);
+    }
+
+    #[test]
+    pub fn memory_errors() {
+        // A 64K memory has only bank 0
+        let mut cpu = CPU65816::new(Memory::new(0x10000));
+        // CLC ; XCE ; LDA $7E1000
+        cpu.memory
+            .poke_bytes(0x8000, &[0x18, 0xFB, 0xAF, 0x00, 0x10, 0x7E]);
+        cpu.registers.pc = 0x8000;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        let error = MemoryError::OutOfBounds {
+            index: 0x7E1000,
+            size: 0x10000,
+        };
+        assert_eq!(cpu.step(), Err(CpuError::Memory { pc: 0x8002, error }));
+        assert_eq!(cpu.registers.pc, 0x8006);
+    }
+
+    #[test]
+    pub fn reset() {
+        let mut cpu = load(&[0x18, 0xFB, 0xC2, 0x30]); // CLC, XCE, REP #$30
+        cpu.memory.poke_bytes_long(0xFFFC, &[0x00, 0x80]);
+        steps(&mut cpu, 3);
+        assert!(cpu.registers.wide_index());
+
+        cpu.reset();
+        assert_eq!(cpu.step().unwrap().cycles, 7);
+        assert!(cpu.registers.emulation);
+        assert!(!cpu.registers.wide_accumulator() && !cpu.registers.wide_index());
+        assert_eq!(cpu.registers.pc, 0x8000);
+        assert_eq!(cpu.registers.sp & 0xFF00, 0x0100);
+    }
+}